@@ -1,9 +1,7 @@
 //! Merge the runtime and compile-time environment.
 //!
 //! See [Issue #20](https://github.com/fizyr/subst/issues/20) for inspiration.
-use std::borrow::Cow;
-
-use subst::map::{fallback, map_value};
+use subst::map::{Layers, Recording};
 
 static STATIC_ENV: &[(&str, &str)] = {
 	&[
@@ -41,9 +39,16 @@ pub fn main() {
 	println!("Substitution using Env, falling back to STATIC_ENV:");
 
 	// `Env` returns `String`s, but `STATIC_ENV` returns `&str` references.
-	let merged = fallback(
-		map_value(subst::Env, Cow::<str>::Owned),
-		map_value(STATIC_ENV, |&value| Cow::<str>::Borrowed(value)),
-	);
+	// The layers convert both to `String`.
+	let merged: Layers = Layers::new()
+		.layer("runtime environment", subst::Env)
+		.layer("compile-time environment", STATIC_ENV);
+	let merged = Recording::new(merged);
 	println!("{}", template.expand(&merged).unwrap());
+	println!();
+
+	println!("Origin of the variables:");
+	for name in merged.found_keys() {
+		println!("${name}: {}", merged.inner().layer_of(&name).unwrap());
+	}
 }
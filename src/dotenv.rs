@@ -0,0 +1,659 @@
+//! Support for loading variables from `.env` files.
+//!
+//! A `.env` file contains one variable assignment per line, like `NAME=value`.
+//! The following syntax is supported:
+//!
+//! * Blank lines and comments starting with `#` are ignored.
+//! * Assignments may start with `export`, which is ignored.
+//! * Unquoted values extend to the end of the line, or to a `#` that follows whitespace.
+//!   Leading and trailing whitespace is removed.
+//! * Values in single quotes are taken literally, without escape sequences or substitution.
+//! * Values in double quotes support the escape sequences `\n`, `\r`, `\t` and `\"`,
+//!   and the escape sequences of the template syntax: `\\`, `\$`, `\{`, `\}`, `\:` and `\=`.
+//! * In unquoted values, only the escape sequences of the template syntax are processed.
+//! * A backslash that does not start an escape sequence is kept literally, so `PATH=C:\tools` works as expected.
+//! * Quoted values may span multiple lines.
+//!
+//! Unquoted and double-quoted values are expanded as templates with [`substitute()`][crate::substitute].
+//! Variables are resolved from the entries earlier in the file first,
+//! and then from the process environment or a variable map of your choice.
+//!
+//! # Example
+//! ```
+//! # use subst::VariableMap;
+//! let source = concat!(
+//!   "# Application settings\n",
+//!   "export BASE=/opt/app\n",
+//!   "LOG_DIR=${BASE}/log # The log directory\n",
+//!   "GREETING=\"Hello\\n${USER:world}!\"\n",
+//!   "RAW='${BASE}'\n",
+//! );
+//! let env = subst::dotenv::from_str_with(source, &subst::NoSubstitution)?;
+//! assert_eq!(env.get("LOG_DIR"), Some("/opt/app/log"));
+//! assert_eq!(env.get("GREETING"), Some("Hello\nworld!"));
+//! assert_eq!(env.get("RAW"), Some("${BASE}"));
+//! # Ok::<(), subst::dotenv::Error>(())
+//! ```
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::error::LookupError;
+use crate::map::IterableVariableMap;
+use crate::VariableMap;
+
+/// Parse a `.env` file, using the process environment for variables that are not defined in the file.
+pub fn from_path(path: impl AsRef<Path>) -> Result<DotEnv, Error> {
+	from_path_with(path, &crate::Env)
+}
+
+/// Parse a `.env` file, using a variable map for variables that are not defined in the file.
+pub fn from_path_with<'a, M>(path: impl AsRef<Path>, variables: &'a M) -> Result<DotEnv, Error>
+where
+	M: VariableMap<'a> + ?Sized,
+	M::Value: AsRef<str>,
+{
+	let source = std::fs::read_to_string(path).map_err(Error::Io)?;
+	from_str_with(&source, variables)
+}
+
+/// Parse the contents of a `.env` file, using the process environment for variables that are not defined in the file.
+pub fn from_str(source: &str) -> Result<DotEnv, Error> {
+	from_str_with(source, &crate::Env)
+}
+
+/// Parse the contents of a `.env` file, using a variable map for variables that are not defined in the file.
+pub fn from_str_with<'a, M>(source: &str, variables: &'a M) -> Result<DotEnv, Error>
+where
+	M: VariableMap<'a> + ?Sized,
+	M::Value: AsRef<str>,
+{
+	let mut parser = Parser {
+		source,
+		position: 0,
+		line: 1,
+	};
+	let mut env = DotEnv::new();
+	while let Some(entry) = parser.next_entry()? {
+		let value = if entry.substitute {
+			let lookup = Interpolation { env: &env, variables };
+			crate::substitute(&entry.value, &lookup).map_err(|error| {
+				Error::Subst(SubstError {
+					line: entry.line,
+					key: entry.key.to_owned(),
+					error,
+				})
+			})?
+		} else {
+			entry.value
+		};
+		env.insert(entry.key, value);
+	}
+	Ok(env)
+}
+
+/// Variables loaded from a `.env` file.
+///
+/// The variables are kept in the order in which they first appear in the file.
+/// If a variable is assigned more than once, the last value is used.
+#[derive(Clone, Default)]
+pub struct DotEnv {
+	/// The variables in order of appearance.
+	entries: Vec<(String, String)>,
+
+	/// The index of each variable in `entries`.
+	index: HashMap<String, usize>,
+}
+
+impl DotEnv {
+	/// Create an empty set of variables.
+	#[inline]
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Get the value of a variable.
+	#[inline]
+	pub fn get(&self, key: &str) -> Option<&str> {
+		let &index = self.index.get(key)?;
+		Some(self.entries[index].1.as_str())
+	}
+
+	/// Set the value of a variable.
+	///
+	/// If the variable already exists, its value is replaced but it keeps its position.
+	pub fn insert(&mut self, key: impl Into<String>, value: impl Into<String>) {
+		let key = key.into();
+		let value = value.into();
+		match self.index.get(&key) {
+			Some(&index) => self.entries[index].1 = value,
+			None => {
+				self.index.insert(key.clone(), self.entries.len());
+				self.entries.push((key, value));
+			},
+		}
+	}
+
+	/// Get the number of variables.
+	#[inline]
+	pub fn len(&self) -> usize {
+		self.entries.len()
+	}
+
+	/// Check if there are no variables.
+	#[inline]
+	pub fn is_empty(&self) -> bool {
+		self.entries.is_empty()
+	}
+
+	/// Iterate over the variables in order.
+	#[inline]
+	pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+		self.entries.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+	}
+
+	/// Consume the variables to get the entries as a vector of key-value pairs.
+	#[inline]
+	pub fn into_vec(self) -> Vec<(String, String)> {
+		self.entries
+	}
+}
+
+impl std::fmt::Debug for DotEnv {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("DotEnv").field("entries", &self.entries).finish()
+	}
+}
+
+/// Compare the variables and their order.
+impl PartialEq for DotEnv {
+	#[inline]
+	fn eq(&self, other: &Self) -> bool {
+		self.entries == other.entries
+	}
+}
+
+impl Eq for DotEnv {}
+
+impl<'a> VariableMap<'a> for DotEnv {
+	type Value = &'a str;
+
+	#[inline]
+	fn get(&'a self, key: &str) -> Option<Self::Value> {
+		DotEnv::get(self, key)
+	}
+}
+
+impl<'a> IterableVariableMap<'a> for DotEnv {
+	type Keys = <[(String, String)] as IterableVariableMap<'a>>::Keys;
+
+	#[inline]
+	fn keys(&'a self) -> Self::Keys {
+		IterableVariableMap::keys(self.entries.as_slice())
+	}
+}
+
+/// The variables used to expand a value: the entries earlier in the file, and then a variable map.
+struct Interpolation<'e, 'm, M: ?Sized> {
+	env: &'e DotEnv,
+	variables: &'m M,
+}
+
+impl<'a, 'm, M> VariableMap<'a> for Interpolation<'_, 'm, M>
+where
+	M: VariableMap<'m> + ?Sized,
+	M::Value: AsRef<str>,
+{
+	type Value = Cow<'a, str>;
+
+	#[inline]
+	fn get(&'a self, key: &str) -> Option<Self::Value> {
+		self.lookup(key).ok().flatten()
+	}
+
+	fn lookup(&'a self, key: &str) -> Result<Option<Self::Value>, LookupError> {
+		if let Some(value) = self.env.get(key) {
+			return Ok(Some(Cow::Borrowed(value)));
+		}
+		let value = self.variables.lookup(key)?;
+		Ok(value.map(|value| Cow::Owned(value.as_ref().to_owned())))
+	}
+}
+
+/// An error that can occur while loading a `.env` file.
+#[derive(Debug)]
+pub enum Error {
+	/// The file could not be read.
+	Io(std::io::Error),
+
+	/// The file contains a syntax error.
+	Syntax(SyntaxError),
+
+	/// Variable substitution failed for a value.
+	Subst(SubstError),
+}
+
+impl Error {
+	/// Get the line number of the error in the file, starting at 1.
+	///
+	/// Returns `None` for I/O errors.
+	#[inline]
+	pub fn line(&self) -> Option<usize> {
+		match self {
+			Self::Io(_) => None,
+			Self::Syntax(e) => Some(e.line),
+			Self::Subst(e) => Some(e.line),
+		}
+	}
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+	#[inline]
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Io(e) => std::fmt::Display::fmt(e, f),
+			Self::Syntax(e) => std::fmt::Display::fmt(e, f),
+			Self::Subst(e) => std::fmt::Display::fmt(e, f),
+		}
+	}
+}
+
+/// A syntax error in a `.env` file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxError {
+	/// The line number of the error, starting at 1.
+	pub line: usize,
+
+	/// A human readable message describing the error.
+	pub message: &'static str,
+}
+
+impl std::error::Error for SyntaxError {}
+
+impl std::fmt::Display for SyntaxError {
+	#[inline]
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "Line {}: {}", self.line, self.message)
+	}
+}
+
+/// Variable substitution failed for a value in a `.env` file.
+#[derive(Debug, Clone)]
+pub struct SubstError {
+	/// The line number where the assignment starts, starting at 1.
+	pub line: usize,
+
+	/// The name of the variable being assigned.
+	pub key: String,
+
+	/// The substitution error.
+	///
+	/// Positions in the error are relative to the value after processing quotes and escape sequences.
+	pub error: crate::Error,
+}
+
+impl std::error::Error for SubstError {}
+
+impl std::fmt::Display for SubstError {
+	#[inline]
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(
+			f,
+			"Line {}: Failed to expand value of {}: {}",
+			self.line, self.key, self.error
+		)
+	}
+}
+
+/// A single assignment from a `.env` file.
+struct Entry<'a> {
+	/// The line where the assignment starts.
+	line: usize,
+
+	/// The name of the variable.
+	key: &'a str,
+
+	/// The value, after processing quotes and escape sequences.
+	value: String,
+
+	/// If true, the value must be expanded as a template.
+	substitute: bool,
+}
+
+/// Parser for the contents of a `.env` file.
+struct Parser<'a> {
+	source: &'a str,
+	position: usize,
+	line: usize,
+}
+
+impl<'a> Parser<'a> {
+	/// Parse the next assignment, skipping blank lines and comments.
+	fn next_entry(&mut self) -> Result<Option<Entry<'a>>, Error> {
+		loop {
+			self.skip_whitespace();
+			match self.peek() {
+				None => return Ok(None),
+				Some('\n') => self.advance(),
+				Some('#') => self.skip_comment(),
+				Some(_) => break,
+			}
+		}
+
+		let line = self.line;
+		if let Some(rest) = self.rest().strip_prefix("export") {
+			if rest.starts_with([' ', '\t']) {
+				self.position += "export".len();
+				self.skip_whitespace();
+			}
+		}
+
+		let key_len = self
+			.rest()
+			.find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+			.unwrap_or(self.rest().len());
+		if key_len == 0 {
+			return Err(self.syntax_error("Expected a variable name"));
+		}
+		let key = &self.rest()[..key_len];
+		self.position += key_len;
+
+		self.skip_whitespace();
+		if self.peek() != Some('=') {
+			return Err(self.syntax_error("Expected '=' after the variable name"));
+		}
+		self.advance();
+		self.skip_whitespace();
+
+		let (value, substitute) = match self.peek() {
+			Some('\'') => (self.single_quoted(line)?, false),
+			Some('"') => (self.double_quoted(line)?, true),
+			_ => (self.unquoted(), true),
+		};
+
+		self.skip_whitespace();
+		match self.peek() {
+			None | Some('\n') => (),
+			Some('#') => self.skip_comment(),
+			Some(_) => return Err(self.syntax_error("Unexpected character after the value")),
+		}
+
+		Ok(Some(Entry {
+			line,
+			key,
+			value,
+			substitute,
+		}))
+	}
+
+	/// Parse an unquoted value up to the end of the line or an inline comment.
+	fn unquoted(&mut self) -> String {
+		let rest = self.rest();
+		let line_end = rest.find(['\n', '\r']).unwrap_or(rest.len());
+		let line = &rest[..line_end];
+		let end = line
+			.char_indices()
+			.find(|&(i, c)| c == '#' && (i == 0 || line[..i].ends_with([' ', '\t'])))
+			.map_or(line.len(), |(i, _c)| i);
+		self.position += end;
+		escape_backslashes(line[..end].trim_end())
+	}
+
+	/// Parse a single-quoted value, taken literally.
+	fn single_quoted(&mut self, line: usize) -> Result<String, Error> {
+		self.advance();
+		let rest = self.rest();
+		let end = rest.find('\'').ok_or(Error::Syntax(SyntaxError {
+			line,
+			message: "Unterminated single-quoted value",
+		}))?;
+		let value = rest[..end].to_owned();
+		self.line += value.matches('\n').count();
+		self.position += end + 1;
+		Ok(value)
+	}
+
+	/// Parse a double-quoted value, with escape sequences.
+	///
+	/// Escape sequences for template syntax are kept, so that they are processed during substitution.
+	fn double_quoted(&mut self, line: usize) -> Result<String, Error> {
+		self.advance();
+		let mut value = String::new();
+		loop {
+			let c = self.peek().ok_or(Error::Syntax(SyntaxError {
+				line,
+				message: "Unterminated double-quoted value",
+			}))?;
+			self.advance();
+			match c {
+				'"' => return Ok(value),
+				'\\' => {
+					let escaped = self.peek().ok_or(Error::Syntax(SyntaxError {
+						line,
+						message: "Unterminated double-quoted value",
+					}))?;
+					self.advance();
+					match escaped {
+						'n' => value.push('\n'),
+						'r' => value.push('\r'),
+						't' => value.push('\t'),
+						'"' => value.push('"'),
+						c => {
+							// Keep escape sequences for template syntax, and escape other backslashes.
+							value.push('\\');
+							if !TEMPLATE_ESCAPES.contains(&c) {
+								value.push('\\');
+							}
+							value.push(c);
+						},
+					}
+				},
+				c => value.push(c),
+			}
+		}
+	}
+
+	/// Get the remaining source text.
+	fn rest(&self) -> &'a str {
+		&self.source[self.position..]
+	}
+
+	/// Get the next character without consuming it.
+	fn peek(&self) -> Option<char> {
+		self.rest().chars().next()
+	}
+
+	/// Consume the next character.
+	fn advance(&mut self) {
+		if let Some(c) = self.peek() {
+			if c == '\n' {
+				self.line += 1;
+			}
+			self.position += c.len_utf8();
+		}
+	}
+
+	/// Skip spaces, tabs and carriage returns.
+	fn skip_whitespace(&mut self) {
+		while let Some(' ' | '\t' | '\r') = self.peek() {
+			self.advance();
+		}
+	}
+
+	/// Skip a comment up to the end of the line.
+	fn skip_comment(&mut self) {
+		while !matches!(self.peek(), None | Some('\n')) {
+			self.advance();
+		}
+	}
+
+	/// Create a syntax error for the current line.
+	fn syntax_error(&self, message: &'static str) -> Error {
+		Error::Syntax(SyntaxError {
+			line: self.line,
+			message,
+		})
+	}
+}
+
+/// The characters that can be escaped with a backslash in a template.
+const TEMPLATE_ESCAPES: &[char] = &['\\', '$', '{', '}', ':', '='];
+
+/// Escape all backslashes that do not start an escape sequence of the template syntax.
+fn escape_backslashes(value: &str) -> String {
+	let mut escaped = String::with_capacity(value.len());
+	let mut chars = value.chars().peekable();
+	while let Some(c) = chars.next() {
+		escaped.push(c);
+		if c == '\\' {
+			match chars.next_if(|c| TEMPLATE_ESCAPES.contains(c)) {
+				Some(c) => escaped.push(c),
+				None => escaped.push('\\'),
+			}
+		}
+	}
+	escaped
+}
+
+#[cfg(test)]
+#[rustfmt::skip]
+mod test {
+	use super::*;
+	use assert2::{assert, check, let_assert};
+
+	#[test]
+	fn test_parse() {
+		let source = concat!(
+			"# comment\n",
+			"\n",
+			"A=1\n",
+			"export B = two words  # comment\n",
+			"C=no#comment\n",
+			"D='single ${A} \\n'\n",
+			"E=\"double ${A}\\t\\\"\\$A\\\\\"\n",
+			"F=\"multi\n",
+			"line\"\n",
+			"G=\n",
+			"H=# only a comment\n",
+			"A=override\r\n",
+		);
+		let_assert!(Ok(env) = from_str_with(source, &crate::NoSubstitution));
+		check!(env.iter().collect::<Vec<_>>() == [
+			("A", "override"),
+			("B", "two words"),
+			("C", "no#comment"),
+			("D", "single ${A} \\n"),
+			("E", "double 1\t\"$A\\"),
+			("F", "multi\nline"),
+			("G", ""),
+			("H", ""),
+		]);
+	}
+
+	#[test]
+	fn test_interpolation() {
+		let variables = [("HOME", "/home/user"), ("BASE", "/ignored")];
+		let source = concat!(
+			"BASE=${HOME}/app\n",
+			"LOG=${BASE}/log\n",
+			"CACHE=${XDG_CACHE_HOME:$HOME/.cache}\n",
+		);
+		let_assert!(Ok(env) = from_str_with(source, &variables));
+		check!(env.get("BASE") == Some("/home/user/app"));
+		check!(env.get("LOG") == Some("/home/user/app/log"));
+		check!(env.get("CACHE") == Some("/home/user/.cache"));
+		check!(env.len() == 3);
+
+		check!(crate::substitute("$LOG", &env).as_deref() == Ok("/home/user/app/log"));
+	}
+
+	#[test]
+	fn test_backslashes() {
+		let source = concat!(
+			"PATH=C:\\tools\\bin\n",
+			"RE=a\\d+\\\\\n",
+			"PRICE=\\$5\n",
+			"QUOTED_PATH=\"C:\\\\tools\\\\$DIR\\x\"\n",
+			"QUOTED_RE=\"a\\d+\\$\"\n",
+			"DIR=dir\\\n",
+		);
+		let_assert!(Ok(env) = from_str_with(source, &[("DIR", "bin")]));
+		check!(env.get("PATH") == Some("C:\\tools\\bin"));
+		check!(env.get("RE") == Some("a\\d+\\"));
+		check!(env.get("PRICE") == Some("$5"));
+		check!(env.get("QUOTED_PATH") == Some("C:\\tools\\bin\\x"));
+		check!(env.get("QUOTED_RE") == Some("a\\d+$"));
+		check!(env.get("DIR") == Some("dir\\"));
+	}
+
+	#[test]
+	fn test_insert() {
+		let mut env = DotEnv::new();
+		env.insert("B", "1");
+		env.insert("A", "2");
+		env.insert("B", "3");
+		check!(env.get("A") == Some("2"));
+		check!(env.get("B") == Some("3"));
+		check!(env.get("C") == None);
+		check!(env.len() == 2);
+		check!(env.iter().collect::<Vec<_>>() == [("B", "3"), ("A", "2")]);
+
+		let mut other = DotEnv::new();
+		other.insert("B", "3");
+		other.insert("A", "2");
+		check!(env == other);
+		check!(format!("{env:?}") == r#"DotEnv { entries: [("B", "3"), ("A", "2")] }"#);
+	}
+
+	#[test]
+	fn test_syntax_errors() {
+		let_assert!(Err(Error::Syntax(e)) = from_str_with("A=1\n=2\n", &crate::NoSubstitution));
+		check!(e.line == 2);
+		check!(e.to_string() == "Line 2: Expected a variable name");
+
+		let_assert!(Err(Error::Syntax(e)) = from_str_with("A 1\n", &crate::NoSubstitution));
+		check!(e.to_string() == "Line 1: Expected '=' after the variable name");
+
+		let_assert!(Err(Error::Syntax(e)) = from_str_with("\nA='1\n\n", &crate::NoSubstitution));
+		check!(e.to_string() == "Line 2: Unterminated single-quoted value");
+
+		let_assert!(Err(Error::Syntax(e)) = from_str_with("A=\"1\nB=2\n", &crate::NoSubstitution));
+		check!(e.to_string() == "Line 1: Unterminated double-quoted value");
+
+		let_assert!(Err(Error::Syntax(e)) = from_str_with("A=\"1\"\nB=\"2\n\"x\n", &crate::NoSubstitution));
+		check!(e.to_string() == "Line 3: Unexpected character after the value");
+	}
+
+	#[test]
+	fn test_subst_error() {
+		let_assert!(Err(e) = from_str_with("A=1\n\nB=\"${A}\n${C}\"\n", &crate::NoSubstitution));
+		assert!(e.line() == Some(3));
+		assert!(e.to_string() == "Line 3: Failed to expand value of B: No such variable: $C");
+	}
+
+	#[test]
+	fn test_variable_not_permitted() {
+		let variables = crate::map::deny_list([("HOME", "/home/user"), ("AWS_SECRET", "hunter2")], ["AWS_*"]);
+		let_assert!(Ok(env) = from_str_with("A=$HOME\nAWS_REGION=eu\nB=$AWS_REGION\n", &variables));
+		check!(env.get("B") == Some("eu"));
+
+		let_assert!(Err(Error::Subst(e)) = from_str_with("A=${AWS_SECRET:none}\n", &variables));
+		let_assert!(crate::Error::NotPermitted(_) = &e.error);
+		check!(e.to_string() == "Line 1: Failed to expand value of A: Variable not permitted: $AWS_SECRET");
+	}
+
+	#[test]
+	fn test_from_path() {
+		let path = std::env::temp_dir().join(format!("subst-dotenv-test-{}.env", std::process::id()));
+		std::fs::write(&path, "A=1\nB=$A$A\n").unwrap();
+		let result = from_path_with(&path, &crate::NoSubstitution);
+		std::fs::remove_file(&path).unwrap();
+		let_assert!(Ok(env) = result);
+		assert!(env.get("B") == Some("11"));
+
+		let_assert!(Err(Error::Io(_)) = from_path("/non-existing/.env"));
+	}
+}
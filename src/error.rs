@@ -1,8 +1,11 @@
 //! Module containing error details.
 
+use crate::map::IterableVariableMap;
+
 /// An error that can occur during variable substitution.
 #[derive(Debug, Clone)]
 #[cfg_attr(test, derive(Eq, PartialEq))]
+#[non_exhaustive]
 pub enum Error {
 	/// The input string contains an invalid escape sequence.
 	InvalidEscapeSequence(InvalidEscapeSequence),
@@ -18,11 +21,21 @@ pub enum Error {
 
 	/// The input string contains a placeholder for a variable that is not in the variable map.
 	NoSuchVariable(NoSuchVariable),
+
+	/// The input string contains a placeholder for a variable that the variable map does not permit access to.
+	NotPermitted(VariableNotPermitted),
+
+	/// The input string contains a placeholder for a variable with a value that refers back to the variable itself.
+	Cycle(VariableCycle),
+
+	/// The input string contains an include directive that could not be processed.
+	Include(IncludeError),
 }
 
 /// An error that can occur while parsing a template.
 #[derive(Debug, Clone)]
 #[cfg_attr(test, derive(Eq, PartialEq))]
+#[non_exhaustive]
 pub enum ParseError {
 	/// The input string contains an invalid escape sequence.
 	InvalidEscapeSequence(InvalidEscapeSequence),
@@ -35,14 +48,27 @@ pub enum ParseError {
 
 	/// The input string contains an unclosed variable placeholder.
 	MissingClosingBrace(MissingClosingBrace),
+
+	/// The input string contains an include directive that could not be processed.
+	Include(IncludeError),
 }
 
 /// An error that can occur while expanding a template.
 #[derive(Debug, Clone)]
 #[cfg_attr(test, derive(Eq, PartialEq))]
+#[non_exhaustive]
 pub enum ExpandError {
 	/// The input string contains a placeholder for a variable that is not in the variable map.
 	NoSuchVariable(NoSuchVariable),
+
+	/// The input string contains a placeholder for a variable that the variable map does not permit access to.
+	NotPermitted(VariableNotPermitted),
+
+	/// The input string contains a placeholder for a variable with a value that refers back to the variable itself.
+	Cycle(VariableCycle),
+
+	/// An error occurred while expanding an included template.
+	Include(IncludeError),
 }
 
 impl From<InvalidEscapeSequence> for Error {
@@ -80,6 +106,27 @@ impl From<NoSuchVariable> for Error {
 	}
 }
 
+impl From<VariableNotPermitted> for Error {
+	#[inline]
+	fn from(other: VariableNotPermitted) -> Self {
+		Self::NotPermitted(other)
+	}
+}
+
+impl From<VariableCycle> for Error {
+	#[inline]
+	fn from(other: VariableCycle) -> Self {
+		Self::Cycle(other)
+	}
+}
+
+impl From<IncludeError> for Error {
+	#[inline]
+	fn from(other: IncludeError) -> Self {
+		Self::Include(other)
+	}
+}
+
 impl From<ParseError> for Error {
 	#[inline]
 	fn from(other: ParseError) -> Self {
@@ -88,6 +135,7 @@ impl From<ParseError> for Error {
 			ParseError::MissingVariableName(e) => Self::MissingVariableName(e),
 			ParseError::UnexpectedCharacter(e) => Self::UnexpectedCharacter(e),
 			ParseError::MissingClosingBrace(e) => Self::MissingClosingBrace(e),
+			ParseError::Include(e) => Self::Include(e),
 		}
 	}
 }
@@ -97,6 +145,9 @@ impl From<ExpandError> for Error {
 	fn from(other: ExpandError) -> Self {
 		match other {
 			ExpandError::NoSuchVariable(e) => Self::NoSuchVariable(e),
+			ExpandError::NotPermitted(e) => Self::NotPermitted(e),
+			ExpandError::Cycle(e) => Self::Cycle(e),
+			ExpandError::Include(e) => Self::Include(e),
 		}
 	}
 }
@@ -129,6 +180,13 @@ impl From<MissingClosingBrace> for ParseError {
 	}
 }
 
+impl From<IncludeError> for ParseError {
+	#[inline]
+	fn from(other: IncludeError) -> Self {
+		Self::Include(other)
+	}
+}
+
 impl From<NoSuchVariable> for ExpandError {
 	#[inline]
 	fn from(other: NoSuchVariable) -> Self {
@@ -136,6 +194,27 @@ impl From<NoSuchVariable> for ExpandError {
 	}
 }
 
+impl From<VariableNotPermitted> for ExpandError {
+	#[inline]
+	fn from(other: VariableNotPermitted) -> Self {
+		Self::NotPermitted(other)
+	}
+}
+
+impl From<VariableCycle> for ExpandError {
+	#[inline]
+	fn from(other: VariableCycle) -> Self {
+		Self::Cycle(other)
+	}
+}
+
+impl From<IncludeError> for ExpandError {
+	#[inline]
+	fn from(other: IncludeError) -> Self {
+		Self::Include(other)
+	}
+}
+
 impl std::error::Error for Error {}
 
 impl std::fmt::Display for Error {
@@ -147,6 +226,9 @@ impl std::fmt::Display for Error {
 			Self::UnexpectedCharacter(e) => e.fmt(f),
 			Self::MissingClosingBrace(e) => e.fmt(f),
 			Self::NoSuchVariable(e) => e.fmt(f),
+			Self::NotPermitted(e) => e.fmt(f),
+			Self::Cycle(e) => e.fmt(f),
+			Self::Include(e) => e.fmt(f),
 		}
 	}
 }
@@ -161,6 +243,7 @@ impl std::fmt::Display for ParseError {
 			Self::MissingVariableName(e) => e.fmt(f),
 			Self::UnexpectedCharacter(e) => e.fmt(f),
 			Self::MissingClosingBrace(e) => e.fmt(f),
+			Self::Include(e) => e.fmt(f),
 		}
 	}
 }
@@ -172,6 +255,9 @@ impl std::fmt::Display for ExpandError {
 	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
 		match self {
 			Self::NoSuchVariable(e) => e.fmt(f),
+			Self::NotPermitted(e) => e.fmt(f),
+			Self::Cycle(e) => e.fmt(f),
+			Self::Include(e) => e.fmt(f),
 		}
 	}
 }
@@ -336,14 +422,87 @@ impl std::fmt::Display for UnexpectedCharacter {
 pub struct ExpectedCharacter {
 	/// A human readable message to describe what is expected.
 	pub(crate) message: &'static str,
+
+	/// The tokens that would have been valid at the position of the unexpected character.
+	pub(crate) tokens: &'static [ExpectedToken],
 }
 
 impl ExpectedCharacter {
+	/// The tokens that are valid after a variable name.
+	pub(crate) const AFTER_NAME: Self = Self {
+		message: "a closing brace ('}'), a colon (':') or a variable name character",
+		tokens: &[
+			ExpectedToken::ClosingBrace,
+			ExpectedToken::Colon,
+			ExpectedToken::NameCharacter,
+		],
+	};
+	/// The tokens that are valid after the scheme of a scheme-prefixed variable.
+	pub(crate) const AFTER_SCHEME: Self = Self {
+		message: "a colon (':') or a variable name character",
+		tokens: &[ExpectedToken::Colon, ExpectedToken::NameCharacter],
+	};
+	/// The tokens that are valid after the key of a scheme-prefixed variable.
+	pub(crate) const AFTER_SCHEME_KEY: Self = Self {
+		message: "a closing brace ('}') or a colon (':')",
+		tokens: &[ExpectedToken::ClosingBrace, ExpectedToken::Colon],
+	};
+
 	/// Get a human readable message to describe what was expected.
 	#[inline]
 	pub fn message(&self) -> &str {
 		self.message
 	}
+
+	/// Get the tokens that would have been valid at the position of the unexpected character.
+	///
+	/// This can be used to offer automatic fixes, like inserting a missing closing brace.
+	#[inline]
+	pub fn tokens(&self) -> &[ExpectedToken] {
+		self.tokens
+	}
+}
+
+impl std::fmt::Display for ExpectedCharacter {
+	#[inline]
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.write_str(self.message)
+	}
+}
+
+/// A token that the parser can expect.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[non_exhaustive]
+pub enum ExpectedToken {
+	/// A closing brace (`}`) to end a variable placeholder.
+	ClosingBrace,
+
+	/// A colon (`:`) to start a default value.
+	Colon,
+
+	/// A character that is valid in a variable name: an ASCII letter, digit or underscore.
+	NameCharacter,
+}
+
+impl ExpectedToken {
+	/// Get the character of the token, if it is a single fixed character.
+	#[inline]
+	pub fn character(&self) -> Option<char> {
+		match self {
+			Self::ClosingBrace => Some('}'),
+			Self::Colon => Some(':'),
+			Self::NameCharacter => None,
+		}
+	}
+
+	/// Get a human readable description of the token.
+	pub fn description(&self) -> &'static str {
+		match self {
+			Self::ClosingBrace => "a closing brace ('}')",
+			Self::Colon => "a colon (':')",
+			Self::NameCharacter => "a variable name character",
+		}
+	}
 }
 
 /// The input string contains an unclosed variable placeholder.
@@ -376,6 +535,52 @@ pub struct NoSuchVariable {
 
 	/// The name of the variable.
 	pub name: String,
+
+	/// The variables that were tried before this one, outermost first.
+	///
+	/// When a variable is not set, its default value is expanded instead.
+	/// If the default value refers to a missing variable too, the error is reported for that variable,
+	/// and the variables that were tried before are recorded here.
+	///
+	/// For example, expanding `${A:${B:${C}}}` without any of the variables being set
+	/// results in an error for `C`, and `A` and `B` are recorded here.
+	pub tried: Vec<TriedVariable>,
+
+	/// Names of similar variables in the variable map, most similar first.
+	///
+	/// This is empty unless suggestions were added with [`Self::with_suggestions()`],
+	/// or one of the `with_suggestions()` functions of the error types that contain this error.
+	pub suggestions: Vec<String>,
+}
+
+impl NoSuchVariable {
+	/// The maximum number of suggestions to add to the error.
+	const MAX_SUGGESTIONS: usize = 3;
+
+	/// Add suggestions for similar variable names found in the variable map.
+	///
+	/// Variable names are ranked by their edit distance to the missing name.
+	/// Names that differ too much are not suggested at all.
+	pub fn with_suggestions<'a, M>(mut self, variables: &'a M) -> Self
+	where
+		M: IterableVariableMap<'a> + ?Sized,
+	{
+		let mut candidates: Vec<(usize, String)> = variables
+			.keys()
+			.filter_map(|key| {
+				let distance = suggestion_distance(&self.name, &key)?;
+				Some((distance, key.into_owned()))
+			})
+			.collect();
+		candidates.sort();
+		candidates.dedup();
+		self.suggestions = candidates
+			.into_iter()
+			.map(|(_distance, key)| key)
+			.take(Self::MAX_SUGGESTIONS)
+			.collect();
+		self
+	}
 }
 
 impl std::error::Error for NoSuchVariable {}
@@ -383,11 +588,326 @@ impl std::error::Error for NoSuchVariable {}
 impl std::fmt::Display for NoSuchVariable {
 	#[inline]
 	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-		write!(f, "No such variable: ${}", self.name)
+		if self.tried.is_empty() {
+			write!(f, "No such variable: {}", DisplayVariable(&self.name))?;
+		} else {
+			write!(f, "None of ")?;
+			for tried in &self.tried {
+				write!(f, "{}, ", DisplayVariable(&tried.name))?;
+			}
+			write!(f, "{} are set", DisplayVariable(&self.name))?;
+		}
+		if let Some((last, rest)) = self.suggestions.split_last() {
+			write!(f, " (did you mean ")?;
+			for (i, name) in rest.iter().enumerate() {
+				if i > 0 {
+					write!(f, ", ")?;
+				}
+				write!(f, "{}", DisplayVariable(name))?;
+			}
+			if !rest.is_empty() {
+				write!(f, " or ")?;
+			}
+			write!(f, "{}?)", DisplayVariable(last))?;
+		}
+		Ok(())
+	}
+}
+
+/// Display a variable name the way it is written in a template.
+///
+/// Names of scheme-prefixed variables contain a colon, and are displayed as `${@scheme:key}`.
+struct DisplayVariable<'a>(&'a str);
+
+impl std::fmt::Display for DisplayVariable<'_> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		if self.0.contains(':') {
+			write!(f, "${{@{}}}", self.0)
+		} else {
+			write!(f, "${}", self.0)
+		}
+	}
+}
+
+/// The input string contains a placeholder for a variable that the variable map does not permit access to.
+///
+/// This happens when a variable map returns [`LookupError::NotPermitted`],
+/// for example because the variable is not in the [allow list][crate::map::allow_list] of the map.
+/// Unlike a missing variable, the default value of the placeholder is not used.
+#[derive(Debug, Clone)]
+#[cfg_attr(test, derive(Eq, PartialEq))]
+pub struct VariableNotPermitted {
+	/// The byte offset within the input where the error occurs.
+	///
+	/// This points to the first character of the name in the input text.
+	pub position: usize,
+
+	/// The name of the variable.
+	pub name: String,
+}
+
+impl std::error::Error for VariableNotPermitted {}
+
+impl std::fmt::Display for VariableNotPermitted {
+	#[inline]
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(f, "Variable not permitted: {}", DisplayVariable(&self.name))
+	}
+}
+
+/// The input string contains a placeholder for a variable with a value that refers back to the variable itself.
+///
+/// This happens when a variable map returns [`LookupError::Cycle`],
+/// for example a [`Recursive`][crate::map::Recursive] map with the variables `A=$B` and `B=$A`.
+#[derive(Debug, Clone)]
+#[cfg_attr(test, derive(Eq, PartialEq))]
+pub struct VariableCycle {
+	/// The byte offset within the input where the error occurs.
+	///
+	/// This points to the first character of the name in the input text.
+	pub position: usize,
+
+	/// The name of the variable.
+	pub name: String,
+
+	/// The names of the variables that form the cycle.
+	///
+	/// The first and the last entry are the same variable.
+	pub cycle: Vec<String>,
+}
+
+impl std::error::Error for VariableCycle {}
+
+impl std::fmt::Display for VariableCycle {
+	#[inline]
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(f, "Variable cycle: ")?;
+		for (i, name) in self.cycle.iter().enumerate() {
+			if i > 0 {
+				write!(f, " -> ")?;
+			}
+			write!(f, "{}", DisplayVariable(name))?;
+		}
+		Ok(())
+	}
+}
+
+/// An error returned by [`VariableMap::lookup()`][crate::VariableMap::lookup].
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+#[non_exhaustive]
+pub enum LookupError {
+	/// The variable map does not permit access to the variable.
+	NotPermitted,
+
+	/// The value of the variable refers back to the variable itself.
+	///
+	/// Contains the names of the variables that form the cycle, starting and ending with the same variable.
+	Cycle(Vec<String>),
+}
+
+impl std::error::Error for LookupError {}
+
+impl std::fmt::Display for LookupError {
+	#[inline]
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::NotPermitted => write!(f, "variable not permitted"),
+			Self::Cycle(cycle) => write!(f, "variable cycle: {}", cycle.join(" -> ")),
+		}
+	}
+}
+
+/// A variable that was tried while expanding a placeholder, but that was not set.
+#[derive(Debug, Clone)]
+#[cfg_attr(test, derive(Eq, PartialEq))]
+pub struct TriedVariable {
+	/// The byte offset within the input where the variable name starts.
+	pub position: usize,
+
+	/// The name of the variable.
+	pub name: String,
+}
+
+/// The input string contains an include directive that could not be processed.
+#[derive(Debug, Clone)]
+#[cfg_attr(test, derive(Eq, PartialEq))]
+pub struct IncludeError {
+	/// The byte offset within the input where the error occurs.
+	///
+	/// This points to the `$` sign of the include directive in the input text.
+	pub position: usize,
+
+	/// The length of the include directive in bytes.
+	pub len: usize,
+
+	/// The IDs of the included templates that lead to the error.
+	///
+	/// The first entry is the template included by the input text,
+	/// the last entry is the template in which the error occurred.
+	/// For include cycles, the last entry is the template that would have been included again.
+	///
+	/// This is empty if the include directive itself is invalid.
+	pub chain: Vec<String>,
+
+	/// The reason why the include failed.
+	pub kind: IncludeErrorKind,
+}
+
+/// The reason why an include directive failed.
+#[derive(Debug, Clone)]
+#[cfg_attr(test, derive(Eq, PartialEq))]
+#[non_exhaustive]
+pub enum IncludeErrorKind {
+	/// The template was parsed without an [`IncludeResolver`][crate::IncludeResolver].
+	NotSupported,
+
+	/// The include directive does not contain a path.
+	MissingPath,
+
+	/// The included templates form a cycle.
+	Cycle,
+
+	/// The resolver failed to load the included template.
+	Resolve(String),
+
+	/// The included template could not be parsed.
+	///
+	/// The position of the inner error is relative to the source of the last template in the include chain.
+	Parse(Box<ParseError>),
+
+	/// The included template could not be expanded.
+	///
+	/// The position of the inner error is relative to the source of the last template in the include chain.
+	Expand(Box<ExpandError>),
+}
+
+impl IncludeError {
+	/// Add a template to the front of the include chain.
+	///
+	/// Used to propagate errors from nested includes to the including template.
+	pub(crate) fn included_from(mut self, id: &str, position: usize, len: usize) -> Self {
+		self.chain.insert(0, id.to_owned());
+		self.position = position;
+		self.len = len;
+		self
+	}
+}
+
+impl std::error::Error for IncludeError {}
+
+impl std::fmt::Display for IncludeError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		struct Chain<'a>(&'a [String]);
+
+		impl std::fmt::Display for Chain<'_> {
+			fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+				for (i, id) in self.0.iter().enumerate() {
+					if i > 0 {
+						write!(f, " -> ")?;
+					}
+					write!(f, "{id:?}")?;
+				}
+				Ok(())
+			}
+		}
+
+		let (via, last) = match self.chain.split_last() {
+			Some((last, via)) => (via, last.as_str()),
+			None => (&[][..], ""),
+		};
+		match &self.kind {
+			IncludeErrorKind::NotSupported => write!(f, "Include directives are not supported here"),
+			IncludeErrorKind::MissingPath => write!(f, "Missing include path"),
+			IncludeErrorKind::Cycle => write!(f, "Include cycle: {}", Chain(&self.chain)),
+			IncludeErrorKind::Resolve(message) => {
+				write!(f, "Failed to include template {last:?}")?;
+				if !via.is_empty() {
+					write!(f, " (included via {})", Chain(via))?;
+				}
+				write!(f, ": {message}")
+			},
+			IncludeErrorKind::Parse(e) => write_nested(f, e, last, via),
+			IncludeErrorKind::Expand(e) => write_nested(f, e, last, via),
+		}?;
+
+		fn write_nested(
+			f: &mut std::fmt::Formatter,
+			error: &dyn std::fmt::Display,
+			last: &str,
+			via: &[String],
+		) -> std::fmt::Result {
+			write!(f, "{error} (in included template {last:?}")?;
+			if !via.is_empty() {
+				write!(f, ", included via {}", Chain(via))?;
+			}
+			write!(f, ")")
+		}
+
+		Ok(())
+	}
+}
+
+impl ExpandError {
+	/// Add suggestions for similar variable names to a [`NoSuchVariable`] error.
+	///
+	/// This also adds suggestions to a [`NoSuchVariable`] error from an included template.
+	/// Other errors are returned unchanged.
+	///
+	/// See [`NoSuchVariable::with_suggestions()`] for details.
+	pub fn with_suggestions<'a, M>(self, variables: &'a M) -> Self
+	where
+		M: IterableVariableMap<'a> + ?Sized,
+	{
+		match self {
+			Self::NoSuchVariable(e) => Self::NoSuchVariable(e.with_suggestions(variables)),
+			Self::Include(e) => Self::Include(e.with_suggestions(variables)),
+			other => other,
+		}
+	}
+}
+
+impl IncludeError {
+	/// Add suggestions to a nested [`NoSuchVariable`] error.
+	fn with_suggestions<'a, M>(mut self, variables: &'a M) -> Self
+	where
+		M: IterableVariableMap<'a> + ?Sized,
+	{
+		if let IncludeErrorKind::Expand(e) = self.kind {
+			self.kind = IncludeErrorKind::Expand(Box::new(e.with_suggestions(variables)));
+		}
+		self
 	}
 }
 
 impl Error {
+	/// Add suggestions for similar variable names to a [`NoSuchVariable`] error.
+	///
+	/// This also adds suggestions to a [`NoSuchVariable`] error from an included template.
+	/// Other errors are returned unchanged.
+	///
+	/// See [`NoSuchVariable::with_suggestions()`] for details.
+	///
+	/// # Example
+	/// ```
+	/// # use std::collections::HashMap;
+	/// let mut variables = HashMap::new();
+	/// variables.insert("DATABASE_URL", "postgres://localhost");
+	///
+	/// let error = subst::substitute("$DATABSE_URL", &variables).unwrap_err();
+	/// let error = error.with_suggestions(&variables);
+	/// assert_eq!(error.to_string(), "No such variable: $DATABSE_URL (did you mean $DATABASE_URL?)");
+	/// ```
+	pub fn with_suggestions<'a, M>(self, variables: &'a M) -> Self
+	where
+		M: IterableVariableMap<'a> + ?Sized,
+	{
+		match self {
+			Self::NoSuchVariable(e) => Self::NoSuchVariable(e.with_suggestions(variables)),
+			Self::Include(e) => Self::Include(e.with_suggestions(variables)),
+			other => other,
+		}
+	}
+
 	/// Get the range in the source text that contains the error.
 	#[inline]
 	pub fn source_range(&self) -> std::ops::Range<usize> {
@@ -400,6 +920,9 @@ impl Error {
 			Self::UnexpectedCharacter(e) => (e.position, e.character.source_len()),
 			Self::MissingClosingBrace(e) => (e.position, 1),
 			Self::NoSuchVariable(e) => (e.position, e.name.len()),
+			Self::NotPermitted(e) => (e.position, e.name.len()),
+			Self::Cycle(e) => (e.position, e.name.len()),
+			Self::Include(e) => (e.position, e.len),
 		};
 		std::ops::Range {
 			start,
@@ -413,59 +936,538 @@ impl Error {
 	/// May panic if the source text is not the original source that contains the error.
 	#[inline]
 	pub fn source_line<'a>(&self, source: &'a str) -> &'a str {
+		let position = self.source_range().start;
+		let start = line_start(source.as_bytes(), position);
+		let end = line_end(source.as_bytes(), position);
+		&source[start..end]
+	}
+
+	/// Get the line of a byte source that contains the error.
+	///
+	/// This is the equivalent of [`Self::source_line()`] for errors from byte templates,
+	/// like [`ByteTemplate`][crate::ByteTemplate] or [`substitute_bytes()`][crate::substitute_bytes].
+	///
+	/// # Panics
+	/// May panic if the source is not the original source that contains the error.
+	#[inline]
+	pub fn source_line_bytes<'a>(&self, source: &'a [u8]) -> &'a [u8] {
 		let position = self.source_range().start;
 		let start = line_start(source, position);
 		let end = line_end(source, position);
 		&source[start..end]
 	}
 
+	/// Get the line and column of the error in the source text.
+	///
+	/// Both the line and column numbers start at 1.
+	/// The column is counted in Unicode characters, not in bytes.
+	///
+	/// A carriage return, a line feed or the combination of both are all considered to be a single line break.
+	///
+	/// # Panics
+	/// May panic if the source text is not the original source that contains the error.
+	#[inline]
+	pub fn line_column(&self, source: &str) -> LineColumn {
+		LineColumn::from_position(source, self.source_range().start)
+	}
+
 	/// Write source highlighting for the error location.
 	///
 	/// The highlighting ends with a newline.
 	///
-	/// Note: this function doesn't print anything if the source line exceeds 60 characters in width.
+	/// If the source line exceeds 60 characters in width,
+	/// only a part of the line around the error is printed, with an ellipsis marking the omitted text.
 	/// For more control over this behaviour, consider using [`Self::source_range()`] and [`Self::source_line()`] instead.
+	///
+	/// # Panics
+	/// May panic if the source text is not the original source that contains the error.
 	#[inline]
 	pub fn write_source_highlighting(&self, f: &mut impl std::fmt::Write, source: &str) -> std::fmt::Result {
-		use unicode_width::UnicodeWidthStr;
-
-		let range = self.source_range();
-		let line = self.source_line(source);
-		if line.width() > 60 {
-			return Ok(());
-		}
-		write!(f, "  {line}\n  ")?;
-		write_underline(f, line, range)?;
-		writeln!(f)
+		write_source_highlighting(f, source, self.source_range())
 	}
 
 	/// Get source highlighting for the error location as a string.
 	///
 	/// The highlighting ends with a newline.
 	///
-	/// Note: this function returns an empty string if the source line exceeds 60 characters in width.
+	/// If the source line exceeds 60 characters in width,
+	/// only a part of the line around the error is included, with an ellipsis marking the omitted text.
+	///
+	/// # Panics
+	/// May panic if the source text is not the original source that contains the error.
 	#[inline]
 	pub fn source_highlighting(&self, source: &str) -> String {
 		let mut output = String::new();
 		self.write_source_highlighting(&mut output, source).unwrap();
 		output
 	}
+
+	/// Write source highlighting for the error location in a byte source.
+	///
+	/// This is the equivalent of [`Self::write_source_highlighting()`] for errors from byte templates.
+	/// Invalid UTF-8 in the source line is printed as escaped `\xNN` sequences,
+	/// and the highlighting is aligned with the escaped text.
+	///
+	/// The highlighting ends with a newline.
+	///
+	/// # Panics
+	/// May panic if the source is not the original source that contains the error.
+	#[inline]
+	pub fn write_source_highlighting_bytes(&self, f: &mut impl std::fmt::Write, source: &[u8]) -> std::fmt::Result {
+		let range = self.source_range();
+		let line_start = line_start(source, range.start);
+		let line = self.source_line_bytes(source);
+		let range = range.start - line_start..(range.end - line_start).min(line.len());
+		let (line, range) = escape_invalid_utf8(line, range);
+		write_source_highlighting(f, &line, range)
+	}
+
+	/// Get source highlighting for the error location in a byte source as a string.
+	///
+	/// This is the equivalent of [`Self::source_highlighting()`] for errors from byte templates.
+	/// Invalid UTF-8 in the source line is printed as escaped `\xNN` sequences,
+	/// and the highlighting is aligned with the escaped text.
+	///
+	/// The highlighting ends with a newline.
+	///
+	/// # Panics
+	/// May panic if the source is not the original source that contains the error.
+	#[inline]
+	pub fn source_highlighting_bytes(&self, source: &[u8]) -> String {
+		let mut output = String::new();
+		self.write_source_highlighting_bytes(&mut output, source).unwrap();
+		output
+	}
+}
+
+/// An error that occurred while performing variable substitution on a string value in a structured document.
+///
+/// Returned by the `substitute_string_values()` functions of the [`json`][crate::json], [`toml`][crate::toml] and [`yaml`][crate::yaml] modules.
+#[cfg(any(feature = "json", feature = "toml", feature = "yaml"))]
+#[cfg_attr(
+	feature = "doc-cfg",
+	doc(cfg(any(feature = "json", feature = "toml", feature = "yaml")))
+)]
+#[derive(Debug, Clone)]
+#[cfg_attr(test, derive(Eq, PartialEq))]
+pub struct DocumentError {
+	/// The path of the string value in the document.
+	pub path: DocumentPath,
+
+	/// The line and column of the string value in the original document, if known.
+	///
+	/// This is only available if the document was parsed from text by this crate and the parser reports the location of values.
+	/// Currently, that is only the case for TOML, so it is always `None` for JSON and YAML.
+	///
+	/// It points at the opening quote of the string value, not at the failed substitution inside the string.
+	pub line_column: Option<LineColumn>,
+
+	/// The error that occurred.
+	///
+	/// Positions in the error are relative to the string value, not to the document.
+	pub error: Box<Error>,
+}
+
+#[cfg(any(feature = "json", feature = "toml", feature = "yaml"))]
+impl std::error::Error for DocumentError {}
+
+#[cfg(any(feature = "json", feature = "toml", feature = "yaml"))]
+impl std::fmt::Display for DocumentError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match (self.path.segments.is_empty(), self.line_column) {
+			(true, None) => write!(f, "{}", self.error),
+			(true, Some(line_column)) => write!(
+				f,
+				"At line {}, column {}: {}",
+				line_column.line, line_column.column, self.error
+			),
+			(false, None) => write!(f, "{}: {}", self.path, self.error),
+			(false, Some(line_column)) => write!(
+				f,
+				"{} (line {}, column {}): {}",
+				self.path, line_column.line, line_column.column, self.error
+			),
+		}
+	}
+}
+
+/// The path of a value in a structured document, like `servers[2].url`.
+#[cfg(any(feature = "json", feature = "toml", feature = "yaml"))]
+#[cfg_attr(
+	feature = "doc-cfg",
+	doc(cfg(any(feature = "json", feature = "toml", feature = "yaml")))
+)]
+#[derive(Debug, Clone, Default, Eq, PartialEq, Hash)]
+pub struct DocumentPath {
+	/// The segments of the path, starting at the root of the document.
+	pub segments: Vec<PathSegment>,
+}
+
+/// A segment of a [`DocumentPath`].
+#[cfg(any(feature = "json", feature = "toml", feature = "yaml"))]
+#[cfg_attr(
+	feature = "doc-cfg",
+	doc(cfg(any(feature = "json", feature = "toml", feature = "yaml")))
+)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum PathSegment {
+	/// The key of a value in a map.
+	Key(String),
+
+	/// The index of a value in a sequence.
+	Index(usize),
+}
+
+#[cfg(any(feature = "json", feature = "toml", feature = "yaml"))]
+impl std::fmt::Display for DocumentPath {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		for (i, segment) in self.segments.iter().enumerate() {
+			match segment {
+				PathSegment::Index(index) => write!(f, "[{index}]")?,
+				PathSegment::Key(key) => {
+					let bare =
+						!key.is_empty() && key.bytes().all(|c| c.is_ascii_alphanumeric() || c == b'_' || c == b'-');
+					if i > 0 {
+						write!(f, ".")?;
+					}
+					if bare {
+						write!(f, "{key}")?;
+					} else {
+						write!(f, "{key:?}")?;
+					}
+				},
+			}
+		}
+		Ok(())
+	}
+}
+
+/// A line and column in a source text.
+///
+/// Both the line and column numbers start at 1.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct LineColumn {
+	/// The line number, starting at 1.
+	pub line: usize,
+
+	/// The column number in Unicode characters, starting at 1.
+	pub column: usize,
+}
+
+impl LineColumn {
+	/// Compute the line and column of a byte offset in a source text.
+	///
+	/// # Panics
+	/// Panics if `position` is out of bounds or not on a character boundary.
+	pub fn from_position(source: &str, position: usize) -> Self {
+		let line_start = line_start(source.as_bytes(), position);
+		let before = &source.as_bytes()[..line_start];
+		let crlf = memchr::memmem::find_iter(before, b"\r\n").count();
+		let line = 1 + memchr::memchr2_iter(b'\n', b'\r', before).count() - crlf;
+		let column = 1 + source[line_start..position].chars().count();
+		Self { line, column }
+	}
+}
+
+impl std::fmt::Display for LineColumn {
+	#[inline]
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(f, "{}:{}", self.line, self.column)
+	}
+}
+
+/// An owned error report that includes the relevant part of the source text.
+///
+/// Unlike [`Error::source_highlighting()`], a report does not need access to the original source when it is displayed.
+/// This makes it suitable for returning from functions or passing through error chains like [`Box<dyn std::error::Error>`].
+///
+/// The report captures the source line that contains the error, the location of the error,
+/// and an optional origin name that describes where the source came from, such as a file path or environment variable name.
+///
+/// # Example
+/// ```
+/// # use std::collections::HashMap;
+/// let source = "[server]\nurl = \"http://${HOST}/\"\n";
+/// let error = subst::substitute(source, &HashMap::<String, String>::new()).unwrap_err();
+/// let report = subst::error::Report::new(error, source).with_origin("config.toml");
+/// assert_eq!(report.to_string(), concat!(
+///   "config.toml:2:17: No such variable: $HOST\n",
+///   "  url = \"http://${HOST}/\"\n",
+///   "                  ^^^^",
+/// ));
+/// ```
+#[derive(Debug, Clone)]
+#[cfg_attr(test, derive(Eq, PartialEq))]
+pub struct Report {
+	/// The error being reported.
+	error: Error,
+
+	/// The name of the origin of the source text.
+	origin: Option<String>,
+
+	/// The location of the error in the source text.
+	line_column: Option<LineColumn>,
+
+	/// The source highlighting for the error, without trailing newline.
+	highlighting: String,
+}
+
+impl Report {
+	/// Create a new report for an error in the given source text.
+	///
+	/// The relevant part of the source text is copied into the report.
+	///
+	/// If the source text is not the original source that contains the error,
+	/// the report may contain the wrong source line.
+	/// If the error location does not exist in the source text at all, the report contains no source context.
+	/// Unlike [`Error::source_highlighting()`], this function never panics.
+	pub fn new(error: impl Into<Error>, source: &str) -> Self {
+		let error = error.into();
+		let range = error.source_range();
+		let (line_column, highlighting) = if range.start <= source.len() && source.is_char_boundary(range.start) {
+			let end = (range.end.min(source.len())..=source.len())
+				.find(|&i| source.is_char_boundary(i))
+				.unwrap_or(source.len());
+			let mut highlighting = String::new();
+			write_source_highlighting(&mut highlighting, source, range.start..end).unwrap();
+			highlighting.truncate(highlighting.trim_end_matches('\n').len());
+			(Some(error.line_column(source)), highlighting)
+		} else {
+			(None, String::new())
+		};
+		Self {
+			error,
+			origin: None,
+			line_column,
+			highlighting,
+		}
+	}
+
+	/// Set the name of the origin of the source text, such as a file path or environment variable name.
+	pub fn with_origin(mut self, origin: impl Into<String>) -> Self {
+		self.origin = Some(origin.into());
+		self
+	}
+
+	/// Get the error being reported.
+	#[inline]
+	pub fn error(&self) -> &Error {
+		&self.error
+	}
+
+	/// Get the name of the origin of the source text, if it was set.
+	#[inline]
+	pub fn origin(&self) -> Option<&str> {
+		self.origin.as_deref()
+	}
+
+	/// Get the line and column of the error in the source text.
+	///
+	/// Returns `None` if the error location was not found in the source text given to [`Self::new()`].
+	#[inline]
+	pub fn line_column(&self) -> Option<LineColumn> {
+		self.line_column
+	}
+
+	/// Consume the report to get the error.
+	#[inline]
+	pub fn into_error(self) -> Error {
+		self.error
+	}
+}
+
+impl std::error::Error for Report {}
+
+impl std::fmt::Display for Report {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match (&self.origin, self.line_column) {
+			(Some(origin), Some(line_column)) => write!(f, "{origin}:{line_column}: ")?,
+			(Some(origin), None) => write!(f, "{origin}: ")?,
+			(None, Some(line_column)) => write!(f, "{line_column}: ")?,
+			(None, None) => (),
+		}
+		write!(f, "{}", self.error)?;
+		if !self.highlighting.is_empty() {
+			write!(f, "\n{}", self.highlighting)?;
+		}
+		Ok(())
+	}
 }
 
-fn line_start(source: &str, position: usize) -> usize {
-	match source[..position].rfind(|c| c == '\n' || c == '\r') {
+impl From<Report> for Error {
+	#[inline]
+	fn from(other: Report) -> Self {
+		other.error
+	}
+}
+
+/// Write source highlighting for a range in the source text.
+fn write_source_highlighting(
+	f: &mut impl std::fmt::Write,
+	source: &str,
+	range: std::ops::Range<usize>,
+) -> std::fmt::Result {
+	let line_start = line_start(source.as_bytes(), range.start);
+	let line = &source[line_start..line_end(source.as_bytes(), range.start)];
+	let range = range.start - line_start..(range.end - line_start).min(line.len());
+
+	let window = highlight_window(line, range.clone(), MAX_HIGHLIGHT_WIDTH);
+	let (prefix, suffix) = match (window.start > 0, window.end < line.len()) {
+		(true, true) => ("…", "…"),
+		(true, false) => ("…", ""),
+		(false, true) => ("", "…"),
+		(false, false) => ("", ""),
+	};
+	write!(f, "  {prefix}{}{suffix}\n  ", &line[window.clone()])?;
+	if !prefix.is_empty() {
+		write!(f, " ")?;
+	}
+	let range = range.start - window.start..range.end.min(window.end) - window.start;
+	write_underline(f, &line[window], range)?;
+	writeln!(f)
+}
+
+/// The maximum width of a source line in source highlighting.
+const MAX_HIGHLIGHT_WIDTH: usize = 60;
+
+/// Get the part of a line to show in source highlighting.
+///
+/// If the line is wider than `max_width`, this returns a window around the highlighted range,
+/// leaving room for an ellipsis on both sides.
+fn highlight_window(line: &str, range: std::ops::Range<usize>, max_width: usize) -> std::ops::Range<usize> {
+	use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+	if line.width() <= max_width {
+		return 0..line.len();
+	}
+
+	let budget = max_width - 2;
+	let mut start = range.start;
+	let mut end = range.start;
+	let mut width = 0;
+
+	// Include as much of the highlighted range as possible.
+	for c in line[range].chars() {
+		let char_width = c.width().unwrap_or(0);
+		if width + char_width > budget {
+			break;
+		}
+		width += char_width;
+		end += c.len_utf8();
+	}
+
+	// Spend half of the remaining width on context before the error, and the rest on context after it.
+	let half = width + (budget - width) / 2;
+	for limit in [half, budget] {
+		for c in line[..start].chars().rev() {
+			let char_width = c.width().unwrap_or(0);
+			if width + char_width > limit {
+				break;
+			}
+			width += char_width;
+			start -= c.len_utf8();
+		}
+		for c in line[end..].chars() {
+			let char_width = c.width().unwrap_or(0);
+			if width + char_width > budget {
+				break;
+			}
+			width += char_width;
+			end += c.len_utf8();
+		}
+	}
+
+	start..end
+}
+
+/// Get the distance used to rank a suggestion for a missing variable name.
+///
+/// Returns `None` if the candidate is too different to be a useful suggestion.
+fn suggestion_distance(name: &str, candidate: &str) -> Option<usize> {
+	if name == candidate {
+		return None;
+	}
+	// Ignore case, so that `$database_url` is a good suggestion for `$DATABASE_URL`.
+	let distance = edit_distance(&name.to_lowercase(), &candidate.to_lowercase());
+	let max_distance = (name.chars().count() / 3).max(1);
+	(distance <= max_distance).then_some(distance)
+}
+
+/// Compute the Levenshtein distance between two strings, counted in Unicode characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+	let b: Vec<char> = b.chars().collect();
+	let mut previous: Vec<usize> = (0..=b.len()).collect();
+	let mut current = vec![0; b.len() + 1];
+	for (i, a) in a.chars().enumerate() {
+		current[0] = i + 1;
+		for (j, &b) in b.iter().enumerate() {
+			let substitute = previous[j] + usize::from(a != b);
+			current[j + 1] = substitute.min(previous[j + 1] + 1).min(current[j] + 1);
+		}
+		std::mem::swap(&mut previous, &mut current);
+	}
+	previous[b.len()]
+}
+
+fn line_start(source: &[u8], position: usize) -> usize {
+	match memchr::memrchr2(b'\n', b'\r', &source[..position]) {
 		Some(line_end) => line_end + 1,
 		None => 0,
 	}
 }
 
-fn line_end(source: &str, position: usize) -> usize {
-	match source[position..].find(|c| c == '\n' || c == '\r') {
+fn line_end(source: &[u8], position: usize) -> usize {
+	match memchr::memchr2(b'\n', b'\r', &source[position..]) {
 		Some(line_end) => position + line_end,
 		None => source.len(),
 	}
 }
 
+/// Render a line of bytes as text, with invalid UTF-8 escaped as `\xNN`.
+///
+/// Also maps a byte range in the line to the corresponding range in the rendered text.
+fn escape_invalid_utf8(line: &[u8], range: std::ops::Range<usize>) -> (String, std::ops::Range<usize>) {
+	use std::fmt::Write;
+
+	let mut output = String::with_capacity(line.len());
+	let mut start = None;
+	let mut end = None;
+	let mut index = 0;
+	while index < line.len() {
+		let (chunk, invalid) = match std::str::from_utf8(&line[index..]) {
+			Ok(valid) => (valid, None),
+			// The unwrap can not panic: we used `e.valid_up_to()` to get the valid UTF-8 slice.
+			Err(e) => (
+				std::str::from_utf8(&line[index..][..e.valid_up_to()]).unwrap(),
+				Some(e.error_len().unwrap_or(line.len() - index - e.valid_up_to())),
+			),
+		};
+		for (i, c) in chunk.char_indices() {
+			if start.is_none() && index + i >= range.start {
+				start = Some(output.len());
+			}
+			if end.is_none() && index + i >= range.end {
+				end = Some(output.len());
+			}
+			output.push(c);
+		}
+		index += chunk.len();
+		for _ in 0..invalid.unwrap_or(0) {
+			if start.is_none() && index >= range.start {
+				start = Some(output.len());
+			}
+			if end.is_none() && index >= range.end {
+				end = Some(output.len());
+			}
+			write!(output, "\\x{:02X}", line[index]).unwrap();
+			index += 1;
+		}
+	}
+	let start = start.unwrap_or(output.len());
+	let end = end.unwrap_or(output.len());
+	(output, start..end)
+}
+
 fn write_underline(f: &mut impl std::fmt::Write, line: &str, range: std::ops::Range<usize>) -> std::fmt::Result {
 	use unicode_width::UnicodeWidthStr;
 	let spaces = line[..range.start].width();
@@ -477,7 +1479,8 @@ fn write_underline(f: &mut impl std::fmt::Write, line: &str, range: std::ops::Ra
 
 #[cfg(test)]
 mod test {
-	use assert2::check;
+	use super::*;
+	use assert2::{check, let_assert};
 
 	#[test]
 	fn test_char_or_byte_quoted_printable() {
@@ -489,4 +1492,212 @@ mod test {
 		check!(Byte(0).quoted_printable().to_string() == r"'\0'");
 		check!(Char('\0').quoted_printable().to_string() == r"'\0'");
 	}
+
+	#[test]
+	fn test_expected_character_message_matches_tokens() {
+		for expected in [
+			ExpectedCharacter::AFTER_NAME,
+			ExpectedCharacter::AFTER_SCHEME,
+			ExpectedCharacter::AFTER_SCHEME_KEY,
+		] {
+			let descriptions: Vec<_> = expected.tokens().iter().map(|token| token.description()).collect();
+			let (last, init) = descriptions.split_last().unwrap();
+			let message = match init {
+				[] => last.to_string(),
+				init => format!("{} or {last}", init.join(", ")),
+			};
+			check!(expected.message() == message);
+		}
+	}
+
+	#[test]
+	fn test_edit_distance() {
+		check!(edit_distance("", "") == 0);
+		check!(edit_distance("abc", "") == 3);
+		check!(edit_distance("HOME", "HOME") == 0);
+		check!(edit_distance("DATABSE_URL", "DATABASE_URL") == 1);
+		check!(edit_distance("kitten", "sitting") == 3);
+		check!(edit_distance("❤x", "x") == 1);
+	}
+
+	#[test]
+	fn test_suggestions() {
+		let variables = [
+			("DATABASE_URL", "postgres://localhost"),
+			("DATABASE_USER", "admin"),
+			("HOME", "/root"),
+			("database_url", "sqlite://"),
+		];
+		let_assert!(Err(e) = crate::substitute("${DATABSE_URL}", &variables));
+		let e = e.with_suggestions(&variables);
+		let_assert!(Error::NoSuchVariable(inner) = &e);
+		check!(inner.suggestions == ["DATABASE_URL", "database_url"]);
+		check!(e.to_string() == "No such variable: $DATABSE_URL (did you mean $DATABASE_URL or $database_url?)");
+
+		let_assert!(Err(e) = crate::substitute("${HOMR}", &variables));
+		check!(e.with_suggestions(&variables).to_string() == "No such variable: $HOMR (did you mean $HOME?)");
+
+		let_assert!(Err(e) = crate::substitute("${PATH}", &variables));
+		check!(e.with_suggestions(&variables).to_string() == "No such variable: $PATH");
+	}
+
+	#[test]
+	fn test_no_such_variable_tried() {
+		let source = "${A:${B:${C}}}";
+		let_assert!(Err(e) = crate::substitute(source, &crate::NoSubstitution));
+		let_assert!(Error::NoSuchVariable(inner) = &e);
+		check!(inner.name == "C");
+		check!(inner.position == 10);
+		check!(
+			inner.tried
+				== [
+					TriedVariable {
+						position: 2,
+						name: "A".into()
+					},
+					TriedVariable {
+						position: 6,
+						name: "B".into()
+					},
+				]
+		);
+		check!(e.to_string() == "None of $A, $B, $C are set");
+		check!(e.source_range() == (10..11));
+
+		let variables = [("B", "b")];
+		let_assert!(Err(e) = crate::substitute("${A:$B/$C}", &variables));
+		check!(e.to_string() == "None of $A, $C are set");
+
+		let_assert!(Err(e) = crate::substitute("${A:x} $C", &variables));
+		check!(e.to_string() == "No such variable: $C");
+	}
+
+	#[test]
+	fn test_suggestions_fallback() {
+		let base = [("USER", "root")];
+		let fallback = [("USERNAME", "root"), ("SHELL", "/bin/sh")];
+		let variables = crate::map::fallback(&base, &fallback);
+		let_assert!(Err(e) = crate::substitute("$USERNAM $SHEL", &variables));
+		check!(e.with_suggestions(&variables).to_string() == "No such variable: $USERNAM (did you mean $USERNAME?)");
+	}
+
+	#[test]
+	fn test_report() {
+		let source = "a: 1\nb: ${B}\n";
+		let_assert!(Err(e) = crate::substitute(source, &crate::NoSubstitution));
+		let report = Report::new(e.clone(), source);
+		check!(report.line_column() == Some(LineColumn { line: 2, column: 6 }));
+		check!(report.origin() == None);
+		check!(report.to_string() == "2:6: No such variable: $B\n  b: ${B}\n       ^");
+
+		let report = report.with_origin("B_FILE");
+		check!(report.origin() == Some("B_FILE"));
+		check!(report.to_string() == "B_FILE:2:6: No such variable: $B\n  b: ${B}\n       ^");
+
+		let boxed: Box<dyn std::error::Error + Send + Sync> = report.into();
+		check!(boxed.to_string() == "B_FILE:2:6: No such variable: $B\n  b: ${B}\n       ^");
+	}
+
+	#[test]
+	fn test_report_wrong_source() {
+		let source = "a: 1\nb: ${B}\n";
+		let_assert!(Err(e) = crate::substitute(source, &crate::NoSubstitution));
+
+		let report = Report::new(e.clone(), "short").with_origin("x");
+		check!(report.line_column() == None);
+		check!(report.to_string() == "x: No such variable: $B");
+
+		let report = Report::new(e.clone(), "0123456789ab");
+		check!(report.to_string() == "1:11: No such variable: $B\n  0123456789ab\n            ^");
+
+		let report = Report::new(e, "012345678❤");
+		check!(report.line_column() == None);
+		check!(report.to_string() == "No such variable: $B");
+	}
+
+	#[test]
+	#[rustfmt::skip]
+	fn test_source_highlighting_bytes() {
+		let source = b"a: \xFF\n\xC3b \xE2\x9D\xA4 ${B}\r\nc: 3";
+		let_assert!(Err(e) = crate::substitute_bytes(source, &crate::NoSubstitution));
+		check!(e.source_line_bytes(source) == b"\xC3b \xE2\x9D\xA4 ${B}");
+		check!(e.source_highlighting_bytes(source) == concat!(
+				"  \\xC3b ❤ ${B}\n",
+				"            ^\n",
+		));
+
+		let source = b"\xFF\xFE$";
+		let_assert!(Err(e) = crate::substitute_bytes(source, &crate::NoSubstitution));
+		check!(e.source_highlighting_bytes(source) == concat!(
+				"  \\xFF\\xFE$\n",
+				"          ^\n",
+		));
+
+		let source = b"${a\xFF}";
+		let_assert!(Err(e) = crate::substitute_bytes(source, &crate::NoSubstitution));
+		check!(e.source_highlighting_bytes(source) == concat!(
+				"  ${a\\xFF}\n",
+				"     ^^^^\n",
+		));
+	}
+
+	#[test]
+	fn test_escape_invalid_utf8() {
+		check!(escape_invalid_utf8(b"abc", 1..2) == ("abc".into(), 1..2));
+		check!(escape_invalid_utf8(b"a\xFFc", 1..2) == ("a\\xFFc".into(), 1..5));
+		check!(escape_invalid_utf8(b"\xE2\x9D\xA4\xE2\x9D", 3..5) == ("❤\\xE2\\x9D".into(), 3..11));
+		check!(escape_invalid_utf8(b"a", 1..1) == ("a".into(), 1..1));
+	}
+
+	#[test]
+	fn test_line_column() {
+		let source = "one\ntwo\r\nthree\rfour ❤ five";
+		check!(LineColumn::from_position(source, 0) == LineColumn { line: 1, column: 1 });
+		check!(LineColumn::from_position(source, 2) == LineColumn { line: 1, column: 3 });
+		check!(LineColumn::from_position(source, 4) == LineColumn { line: 2, column: 1 });
+		check!(LineColumn::from_position(source, 9) == LineColumn { line: 3, column: 1 });
+		check!(LineColumn::from_position(source, 15) == LineColumn { line: 4, column: 1 });
+		check!(LineColumn::from_position(source, 23) == LineColumn { line: 4, column: 7 });
+		check!(LineColumn::from_position(source, 23).to_string() == "4:7");
+
+		let source = "a: 1\nb: ${B}";
+		let_assert!(Err(e) = crate::substitute(source, &crate::NoSubstitution));
+		check!(e.line_column(source) == LineColumn { line: 2, column: 6 });
+	}
+
+	#[test]
+	#[rustfmt::skip]
+	fn test_source_highlighting_multiple_lines() {
+		let source = "a: 1\nb: ${B}\n";
+		let_assert!(Err(e) = crate::substitute(source, &crate::NoSubstitution));
+		check!(e.source_highlighting(source) == concat!(
+				"  b: ${B}\n",
+				"       ^\n",
+		));
+	}
+
+	#[test]
+	#[rustfmt::skip]
+	fn test_source_highlighting_long_line() {
+		let source = "key: \"the value of this key is very long and contains ${A_VARIABLE} somewhere in the middle of it all\"";
+		let_assert!(Err(e) = crate::substitute(source, &crate::NoSubstitution));
+		check!(e.source_highlighting(source) == concat!(
+				"  …ery long and contains ${A_VARIABLE} somewhere in the middl…\n",
+				"                           ^^^^^^^^^^\n",
+		));
+
+		let source = "key: ${A_VARIABLE} and then a very long explanation of all the things that follow";
+		let_assert!(Err(e) = crate::substitute(source, &crate::NoSubstitution));
+		check!(e.source_highlighting(source) == concat!(
+				"  key: ${A_VARIABLE} and then a very long explanation of all…\n",
+				"         ^^^^^^^^^^\n",
+		));
+
+		let source = "a very long explanation of all the things that precede the error: ${A_VARIABLE}";
+		let_assert!(Err(e) = crate::substitute(source, &crate::NoSubstitution));
+		check!(e.source_highlighting(source) == concat!(
+				"  …on of all the things that precede the error: ${A_VARIABLE}\n",
+				"                                                  ^^^^^^^^^^\n",
+		));
+	}
 }
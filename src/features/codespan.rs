@@ -0,0 +1,134 @@
+//! Support for reporting errors with [`codespan_reporting`].
+//!
+//! Use [`diagnostic()`] to turn an [`Error`] into a [`Diagnostic`] for a file in your file database.
+//! This allows you to report errors from many template files in one run, with file names and source snippets.
+//!
+//! # Example
+//! ```
+//! # use std::collections::HashMap;
+//! use codespan_reporting::files::SimpleFiles;
+//! use codespan_reporting::term::{self, termcolor::NoColor};
+//!
+//! let mut files = SimpleFiles::new();
+//! let variables: HashMap<&str, &str> = HashMap::new();
+//! let mut output = NoColor::new(Vec::new());
+//!
+//! for (name, source) in [("a.conf", "path = ${DIR:/etc}/a"), ("b.conf", "path = ${DIR}/b")] {
+//!   let file_id = files.add(name, source);
+//!   if let Err(error) = subst::substitute(source, &variables) {
+//!     let diagnostic = subst::codespan::diagnostic(&error, file_id, source);
+//!     term::emit(&mut output, &term::Config::default(), &files, &diagnostic).unwrap();
+//!   }
+//! }
+//!
+//! let output = String::from_utf8(output.into_inner()).unwrap();
+//! assert!(output.contains("b.conf"));
+//! ```
+
+use codespan_reporting::diagnostic::{Diagnostic, Label};
+
+use super::diagnostic::{code, help, label_message, ErrorRef};
+use crate::Error;
+
+/// Create a [`Diagnostic`] for an error in the given file.
+///
+/// The `source` must be the source text of the file that contains the error.
+/// It is used to add secondary labels for the enclosing variables when the error is inside a default value.
+///
+/// # Panics
+/// May panic if the source text is not the original source that contains the error.
+pub fn diagnostic<FileId: Copy>(error: &Error, file_id: FileId, source: &str) -> Diagnostic<FileId> {
+	let range = error.source_range();
+	let error_ref = ErrorRef::from(error);
+	let mut labels = vec![Label::primary(file_id, range.clone()).with_message(label_message(error_ref))];
+	let tried = match error {
+		Error::NoSuchVariable(e) => e.tried.as_slice(),
+		_ => &[],
+	};
+	for enclosing in crate::template::enclosing_defaults(source.as_bytes(), range.start) {
+		let message = if tried.iter().any(|tried| enclosing.contains(&tried.position)) {
+			"this variable is not set either"
+		} else {
+			"in the default value of this variable"
+		};
+		labels.push(Label::secondary(file_id, enclosing).with_message(message));
+	}
+
+	Diagnostic::error()
+		.with_message(error.to_string())
+		.with_code(code(error_ref))
+		.with_labels(labels)
+		.with_notes(
+			help(error_ref)
+				.map(|help| format!("help: {help}"))
+				.into_iter()
+				.collect(),
+		)
+}
+
+#[cfg(test)]
+#[rustfmt::skip]
+mod test {
+	use std::collections::BTreeMap;
+
+	use super::*;
+	use crate::template::enclosing_defaults;
+	use assert2::{assert, let_assert};
+	use codespan_reporting::diagnostic::LabelStyle;
+	use codespan_reporting::files::SimpleFiles;
+	use codespan_reporting::term::{self, termcolor::NoColor};
+
+	#[test]
+	#[allow(clippy::single_range_in_vec_init)]
+	fn test_enclosing_defaults() {
+		assert!(enclosing_defaults(b"${A}", 2) == []);
+		assert!(enclosing_defaults(b"${A:${B}}", 6) == [0..4]);
+		assert!(enclosing_defaults(b"${A:x} ${B:${C:{}${D}}}", 19) == [7..11, 11..15]);
+		assert!(enclosing_defaults(b"${A:\\${B:${C}}", 12) == [0..4]);
+		assert!(enclosing_defaults(b"${@env:HOME:${@file:/a/b:x}}", 25) == [0..12, 12..25]);
+		assert!(enclosing_defaults(b"${A:=${B:${C}}}", 11) == [0..5, 5..9]);
+		assert!(enclosing_defaults(b"${A:x\\q${B}}", 5) == [0..4]);
+	}
+
+	#[test]
+	fn test_diagnostic() {
+		let source = "Hello ${A:${B:${C}}}!";
+		let_assert!(Err(error) = crate::substitute(source, &BTreeMap::<String, String>::new()));
+		let diagnostic = diagnostic(&error, 7, source);
+		assert!(diagnostic.message == "None of $A, $B, $C are set");
+		assert!(diagnostic.code.as_deref() == Some("subst::no_such_variable"));
+		assert!(diagnostic.labels.len() == 3);
+		assert!(diagnostic.labels[0].style == LabelStyle::Primary);
+		assert!(diagnostic.labels[0].file_id == 7);
+		assert!(diagnostic.labels[0].range == (16..17));
+		assert!(diagnostic.labels[1].style == LabelStyle::Secondary);
+		assert!(diagnostic.labels[1].range == (6..10));
+		assert!(diagnostic.labels[1].message == "this variable is not set either");
+		assert!(diagnostic.labels[2].range == (10..14));
+
+		let source = "${A:x${B}}";
+		let_assert!(Err(error) = crate::substitute(source, &BTreeMap::<String, String>::new()));
+		let_assert!(Error::NoSuchVariable(mut inner) = error);
+		inner.tried.clear();
+		let diagnostic = super::diagnostic(&Error::NoSuchVariable(inner), 7, source);
+		assert!(diagnostic.labels[1].message == "in the default value of this variable");
+	}
+
+	#[test]
+	fn test_emit_multiple_files() {
+		let mut files = SimpleFiles::new();
+		let mut output = NoColor::new(Vec::new());
+		let variables: BTreeMap<&str, &str> = [("A", "a")].into_iter().collect();
+		for (name, source) in [("one.conf", "x = ${A}\ny = $B"), ("two.conf", "x = ${A:\\q}")] {
+			let file_id = files.add(name, source);
+			let_assert!(Err(error) = crate::substitute(source, &variables));
+			term::emit(&mut output, &term::Config::default(), &files, &diagnostic(&error, file_id, source)).unwrap();
+		}
+		let output = String::from_utf8(output.into_inner()).unwrap();
+		assert!(output.contains("error[subst::no_such_variable]: No such variable: $B"));
+		assert!(output.contains("┌─ one.conf:2:6"));
+		assert!(output.contains("error[subst::invalid_escape_sequence]: Invalid escape sequence: \\q"));
+		assert!(output.contains("┌─ two.conf:1:9"));
+		assert!(output.contains("in the default value of this variable"));
+	}
+}
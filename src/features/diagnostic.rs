@@ -0,0 +1,153 @@
+//! Shared messages for the diagnostic integrations.
+
+use crate::error::{
+	ExpandError,
+	IncludeError,
+	IncludeErrorKind,
+	InvalidEscapeSequence,
+	MissingClosingBrace,
+	MissingVariableName,
+	NoSuchVariable,
+	ParseError,
+	UnexpectedCharacter,
+	VariableCycle,
+	VariableNotPermitted,
+};
+use crate::Error;
+
+/// A borrowed [`Error`], [`ParseError`] or [`ExpandError`].
+///
+/// This allows the diagnostic integrations to handle all error types without cloning them.
+#[derive(Debug, Clone, Copy)]
+pub enum ErrorRef<'a> {
+	InvalidEscapeSequence(&'a InvalidEscapeSequence),
+	MissingVariableName(&'a MissingVariableName),
+	UnexpectedCharacter(&'a UnexpectedCharacter),
+	MissingClosingBrace(&'a MissingClosingBrace),
+	NoSuchVariable(&'a NoSuchVariable),
+	NotPermitted(&'a VariableNotPermitted),
+	Cycle(&'a VariableCycle),
+	Include(&'a IncludeError),
+}
+
+impl<'a> From<&'a Error> for ErrorRef<'a> {
+	fn from(other: &'a Error) -> Self {
+		match other {
+			Error::InvalidEscapeSequence(e) => Self::InvalidEscapeSequence(e),
+			Error::MissingVariableName(e) => Self::MissingVariableName(e),
+			Error::UnexpectedCharacter(e) => Self::UnexpectedCharacter(e),
+			Error::MissingClosingBrace(e) => Self::MissingClosingBrace(e),
+			Error::NoSuchVariable(e) => Self::NoSuchVariable(e),
+			Error::NotPermitted(e) => Self::NotPermitted(e),
+			Error::Cycle(e) => Self::Cycle(e),
+			Error::Include(e) => Self::Include(e),
+		}
+	}
+}
+
+impl<'a> From<&'a ParseError> for ErrorRef<'a> {
+	fn from(other: &'a ParseError) -> Self {
+		match other {
+			ParseError::InvalidEscapeSequence(e) => Self::InvalidEscapeSequence(e),
+			ParseError::MissingVariableName(e) => Self::MissingVariableName(e),
+			ParseError::UnexpectedCharacter(e) => Self::UnexpectedCharacter(e),
+			ParseError::MissingClosingBrace(e) => Self::MissingClosingBrace(e),
+			ParseError::Include(e) => Self::Include(e),
+		}
+	}
+}
+
+impl<'a> From<&'a ExpandError> for ErrorRef<'a> {
+	fn from(other: &'a ExpandError) -> Self {
+		match other {
+			ExpandError::NoSuchVariable(e) => Self::NoSuchVariable(e),
+			ExpandError::NotPermitted(e) => Self::NotPermitted(e),
+			ExpandError::Cycle(e) => Self::Cycle(e),
+			ExpandError::Include(e) => Self::Include(e),
+		}
+	}
+}
+
+impl std::fmt::Display for ErrorRef<'_> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::InvalidEscapeSequence(e) => e.fmt(f),
+			Self::MissingVariableName(e) => e.fmt(f),
+			Self::UnexpectedCharacter(e) => e.fmt(f),
+			Self::MissingClosingBrace(e) => e.fmt(f),
+			Self::NoSuchVariable(e) => e.fmt(f),
+			Self::NotPermitted(e) => e.fmt(f),
+			Self::Cycle(e) => e.fmt(f),
+			Self::Include(e) => e.fmt(f),
+		}
+	}
+}
+
+/// Get the diagnostic code for an error.
+pub fn code(error: ErrorRef) -> &'static str {
+	match error {
+		ErrorRef::InvalidEscapeSequence(_) => "subst::invalid_escape_sequence",
+		ErrorRef::MissingVariableName(_) => "subst::missing_variable_name",
+		ErrorRef::UnexpectedCharacter(_) => "subst::unexpected_character",
+		ErrorRef::MissingClosingBrace(_) => "subst::missing_closing_brace",
+		ErrorRef::NoSuchVariable(_) => "subst::no_such_variable",
+		ErrorRef::NotPermitted(_) => "subst::variable_not_permitted",
+		ErrorRef::Cycle(_) => "subst::variable_cycle",
+		ErrorRef::Include(_) => "subst::include",
+	}
+}
+
+/// Get help text for an error.
+pub fn help(error: ErrorRef) -> Option<String> {
+	match error {
+		ErrorRef::InvalidEscapeSequence(_) => Some(
+			"only `\\\\`, `\\$`, `\\{`, `\\}`, `\\:` and `\\=` are valid escape sequences, so write a literal backslash as `\\\\`"
+				.into(),
+		),
+		ErrorRef::MissingVariableName(_) => {
+			Some("a variable name must follow the `$`, so escape a literal `$` as `\\$`".into())
+		},
+		ErrorRef::UnexpectedCharacter(_) => Some(
+			"variable names can only contain ASCII letters, digits and underscores, and are followed by `}` or `:default}`"
+				.into(),
+		),
+		ErrorRef::MissingClosingBrace(_) => Some("add a closing brace (`}`), or escape a literal `$` as `\\$`".into()),
+		ErrorRef::NoSuchVariable(e) => match e.suggestions.first() {
+			Some(suggestion) => Some(format!("did you mean `{suggestion}`?")),
+			None if e.name.contains(':') => Some(format!(
+				"make sure `{0}` can be resolved, or add a default value with `${{@{0}:default}}`",
+				e.name
+			)),
+			None => Some(format!(
+				"set the variable `{0}`, or add a default value with `${{{0}:default}}`",
+				e.name
+			)),
+		},
+		ErrorRef::NotPermitted(_) => Some("the variable map does not permit access to this variable".into()),
+		ErrorRef::Cycle(_) => Some("change the value of one of the variables to break the cycle".into()),
+		ErrorRef::Include(e) => match &e.kind {
+			IncludeErrorKind::NotSupported => {
+				Some("parse the template with an include resolver to enable include directives".into())
+			},
+			IncludeErrorKind::MissingPath => Some("add the path of the template to include: `${@include path}`".into()),
+			IncludeErrorKind::Cycle => Some("remove one of the include directives to break the cycle".into()),
+			IncludeErrorKind::Resolve(_) => None,
+			IncludeErrorKind::Parse(_) => None,
+			IncludeErrorKind::Expand(_) => None,
+		},
+	}
+}
+
+/// Get the message for the label at the location of an error.
+pub fn label_message(error: ErrorRef) -> String {
+	match error {
+		ErrorRef::InvalidEscapeSequence(_) => "invalid escape sequence".into(),
+		ErrorRef::MissingVariableName(_) => "expected a variable name".into(),
+		ErrorRef::UnexpectedCharacter(e) => format!("expected {}", e.expected.message()),
+		ErrorRef::MissingClosingBrace(_) => "unclosed brace".into(),
+		ErrorRef::NoSuchVariable(_) => "variable is not set".into(),
+		ErrorRef::NotPermitted(_) => "variable is not permitted".into(),
+		ErrorRef::Cycle(_) => "variable refers to itself".into(),
+		ErrorRef::Include(_) => "included here".into(),
+	}
+}
@@ -1,5 +1,7 @@
 use indexmap::IndexMap;
+use std::borrow::Cow;
 
+use crate::map::{IterableVariableMap, VariableMapMut};
 use crate::VariableMap;
 
 impl<'a, V: 'a> VariableMap<'a> for IndexMap<&str, V> {
@@ -11,6 +13,16 @@ impl<'a, V: 'a> VariableMap<'a> for IndexMap<&str, V> {
 	}
 }
 
+impl<'a, 'k: 'a, V: 'a> IterableVariableMap<'a> for IndexMap<&'k str, V> {
+	#[allow(clippy::type_complexity)]
+	type Keys = std::iter::Map<indexmap::map::Keys<'a, &'k str, V>, fn(&'a &'k str) -> Cow<'a, str>>;
+
+	#[inline]
+	fn keys(&'a self) -> Self::Keys {
+		self.keys().map(|key| Cow::Borrowed(*key))
+	}
+}
+
 impl<'a, V: 'a> VariableMap<'a> for IndexMap<String, V> {
 	type Value = &'a V;
 
@@ -20,6 +32,23 @@ impl<'a, V: 'a> VariableMap<'a> for IndexMap<String, V> {
 	}
 }
 
+impl<'a, V: 'a> IterableVariableMap<'a> for IndexMap<String, V> {
+	#[allow(clippy::type_complexity)]
+	type Keys = std::iter::Map<indexmap::map::Keys<'a, String, V>, fn(&'a String) -> Cow<'a, str>>;
+
+	#[inline]
+	fn keys(&'a self) -> Self::Keys {
+		self.keys().map(|key| Cow::Borrowed(key.as_str()))
+	}
+}
+
+impl<T, V: From<T>> VariableMapMut<T> for IndexMap<String, V> {
+	#[inline]
+	fn set(&mut self, key: &str, value: T) {
+		self.insert(key.to_owned(), value.into());
+	}
+}
+
 #[cfg(test)]
 #[rustfmt::skip]
 mod test {
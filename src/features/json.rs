@@ -1,7 +1,10 @@
 //! Support for variable substitution in JSON data.
 
+use std::borrow::Cow;
+
 use serde::de::DeserializeOwned;
 
+use crate::error::{DocumentError, DocumentPath, LineColumn, PathSegment};
 use crate::VariableMap;
 
 /// Parse a struct from JSON data, after performing variable substitution on string values.
@@ -35,13 +38,21 @@ where
 }
 
 /// Perform variable substitution on string values of a JSON value.
-pub fn substitute_string_values<'a, M>(value: &mut serde_json::Value, variables: &'a M) -> Result<(), crate::Error>
+///
+/// If substitution fails, the returned error contains the path of the string value that caused the error.
+pub fn substitute_string_values<'a, M>(value: &mut serde_json::Value, variables: &'a M) -> Result<(), DocumentError>
 where
 	M: VariableMap<'a> + ?Sized,
 	M::Value: AsRef<str>,
 {
-	visit_string_values(value, |value| {
-		*value = crate::substitute(value.as_str(), variables)?;
+	visit_string_values(value, &mut Vec::new(), |value, path| {
+		*value = crate::substitute(value.as_str(), variables).map_err(|error| DocumentError {
+			path: DocumentPath {
+				segments: path.to_vec(),
+			},
+			line_column: None,
+			error: Box::new(error),
+		})?;
 		Ok(())
 	})
 }
@@ -52,8 +63,8 @@ pub enum Error {
 	/// An error occurred while parsing JSON.
 	Json(serde_json::Error),
 
-	/// An error occurred while performing variable substitution.
-	Subst(crate::Error),
+	/// An error occurred while performing variable substitution on a string value.
+	Subst(DocumentError),
 }
 
 impl From<serde_json::Error> for Error {
@@ -63,13 +74,38 @@ impl From<serde_json::Error> for Error {
 	}
 }
 
-impl From<crate::Error> for Error {
+impl From<DocumentError> for Error {
 	#[inline]
-	fn from(other: crate::Error) -> Self {
+	fn from(other: DocumentError) -> Self {
 		Self::Subst(other)
 	}
 }
 
+impl Error {
+	/// Get the path of the string value that caused a substitution error.
+	///
+	/// Returns `None` if the error is not a substitution error.
+	#[inline]
+	pub fn path(&self) -> Option<&DocumentPath> {
+		match self {
+			Self::Subst(e) => Some(&e.path),
+			_ => None,
+		}
+	}
+
+	/// Get the line and column in the original document of the string value that caused a substitution error.
+	///
+	/// This always returns `None` for now, because the JSON parser does not report the location of values.
+	/// Use [`Self::path()`] to find the string value that caused the error.
+	#[inline]
+	pub fn line_column(&self) -> Option<LineColumn> {
+		match self {
+			Self::Subst(e) => e.line_column,
+			_ => None,
+		}
+	}
+}
+
 impl std::error::Error for Error {}
 
 impl std::fmt::Display for Error {
@@ -82,31 +118,107 @@ impl std::fmt::Display for Error {
 	}
 }
 
+/// Look up variables in the top-level object of a JSON value.
+///
+/// A variable is looked up as a top-level key first.
+/// If there is no such key, the variable name is interpreted as a dotted path into the document,
+/// where each segment is an object key or an array index, like `servers.0.url`.
+///
+/// Strings, numbers and booleans are rendered as strings.
+/// Null values, arrays and objects are treated as missing variables.
+///
+/// Dotted paths are not valid variable names in a template,
+/// but they can be used as the key of a scheme-prefixed variable with a [`Router`][crate::map::Router].
+///
+/// # Example
+/// ```
+/// # use subst::map::Router;
+/// let vars: serde_json::Value = serde_json::from_str(r#"
+///   { "name": "app", "port": 8080, "servers": [{ "url": "http://localhost" }] }
+/// "#).unwrap();
+/// assert_eq!(subst::substitute("$name:$port", &vars).unwrap(), "app:8080");
+///
+/// let variables: Router = Router::new().route("vars", vars);
+/// assert_eq!(subst::substitute("${@vars:servers.0.url}", &variables).unwrap(), "http://localhost");
+/// ```
+impl<'a> VariableMap<'a> for serde_json::Value {
+	type Value = Cow<'a, str>;
+
+	#[inline]
+	fn get(&'a self, key: &str) -> Option<Self::Value> {
+		VariableMap::get(self.as_object()?, key)
+	}
+}
+
+/// Look up variables in a JSON object.
+///
+/// Variables are resolved the same as for a [`serde_json::Value`].
+impl<'a> VariableMap<'a> for serde_json::Map<String, serde_json::Value> {
+	type Value = Cow<'a, str>;
+
+	fn get(&'a self, key: &str) -> Option<Self::Value> {
+		scalar_to_string(find_variable(self, key)?)
+	}
+}
+
 /// Recursively apply a function to all string values in a JSON value.
-fn visit_string_values<F, E>(value: &mut serde_json::Value, fun: F) -> Result<(), E>
+///
+/// The `path` is extended with the path of each value while it is being visited.
+fn visit_string_values<F, E>(value: &mut serde_json::Value, path: &mut Vec<PathSegment>, fun: F) -> Result<(), E>
 where
-	F: Copy + Fn(&mut String) -> Result<(), E>,
+	F: Copy + Fn(&mut String, &[PathSegment]) -> Result<(), E>,
 {
 	match value {
 		serde_json::Value::Null => Ok(()),
 		serde_json::Value::Bool(_) => Ok(()),
 		serde_json::Value::Number(_) => Ok(()),
-		serde_json::Value::String(val) => fun(val),
+		serde_json::Value::String(val) => fun(val, path),
 		serde_json::Value::Array(seq) => {
-			for value in seq {
-				visit_string_values(value, fun)?;
+			for (index, value) in seq.iter_mut().enumerate() {
+				path.push(PathSegment::Index(index));
+				visit_string_values(value, path, fun)?;
+				path.pop();
 			}
 			Ok(())
 		},
 		serde_json::Value::Object(map) => {
-			for value in map.values_mut() {
-				visit_string_values(value, fun)?;
+			for (key, value) in map.iter_mut() {
+				path.push(PathSegment::Key(key.clone()));
+				visit_string_values(value, path, fun)?;
+				path.pop();
 			}
 			Ok(())
 		},
 	}
 }
 
+/// Find the value of a variable by top-level key or dotted path.
+fn find_variable<'a>(map: &'a serde_json::Map<String, serde_json::Value>, key: &str) -> Option<&'a serde_json::Value> {
+	if let Some(value) = map.get(key) {
+		return Some(value);
+	}
+	let (first, rest) = key.split_once('.')?;
+	rest.split('.').try_fold(map.get(first)?, |value, segment| match value {
+		serde_json::Value::Object(map) => map.get(segment),
+		serde_json::Value::Array(seq) => seq.get(segment.parse::<usize>().ok()?),
+		_ => None,
+	})
+}
+
+/// Render a scalar JSON value as string.
+///
+/// Returns `None` for null values, arrays and objects.
+fn scalar_to_string(value: &serde_json::Value) -> Option<Cow<'_, str>> {
+	match value {
+		serde_json::Value::Null => None,
+		serde_json::Value::Bool(value) => Some(Cow::Owned(value.to_string())),
+		serde_json::Value::Number(value) => Some(Cow::Owned(value.to_string())),
+		serde_json::Value::String(value) => Some(Cow::Borrowed(value)),
+		serde_json::Value::Array(_) => None,
+		serde_json::Value::Object(_) => None,
+	}
+}
+
 #[cfg(test)]
 #[rustfmt::skip]
 mod test {
@@ -215,4 +327,47 @@ mod test {
 		assert!(parsed.bar == "aap");
 		assert!(parsed.baz == "noot/with/stuff");
 	}
+
+	#[test]
+	fn test_error_path() {
+		let variables: HashMap<&str, &str> = HashMap::new();
+		let_assert!(Err(e) = from_str::<serde_json::Value, _>(r#"{ "servers": [{ "url": "a" }, { "url": "http://$HOST/" }] }"#, &variables));
+		let_assert!(Some(path) = e.path());
+		assert!(path.segments == [PathSegment::Key("servers".into()), PathSegment::Index(1), PathSegment::Key("url".into())]);
+		assert!(e.line_column() == None);
+		assert!(e.to_string() == "servers[1].url: No such variable: $HOST");
+
+		let_assert!(Err(e) = from_str::<serde_json::Value, _>(r#""$HOST""#, &variables));
+		assert!(e.to_string() == "No such variable: $HOST");
+	}
+
+	#[test]
+	fn test_value_as_variable_map() {
+		let_assert!(Ok(variables) = serde_json::from_str::<serde_json::Value>(r#"
+			{
+				"name": "app",
+				"port": 8080,
+				"debug": false,
+				"ratio": 0.5,
+				"none": null,
+				"dotted.key": "top",
+				"servers": [{ "url": "http://localhost" }],
+				"dotted": { "key": "nested" }
+			}"#
+		));
+		assert!(VariableMap::get(&variables, "name").as_deref() == Some("app"));
+		assert!(VariableMap::get(&variables, "port").as_deref() == Some("8080"));
+		assert!(VariableMap::get(&variables, "debug").as_deref() == Some("false"));
+		assert!(VariableMap::get(&variables, "ratio").as_deref() == Some("0.5"));
+		assert!(VariableMap::get(&variables, "none") == None);
+		assert!(VariableMap::get(&variables, "servers") == None);
+		assert!(VariableMap::get(&variables, "servers.0.url").as_deref() == Some("http://localhost"));
+		assert!(VariableMap::get(&variables, "servers.1.url") == None);
+		assert!(VariableMap::get(&variables, "servers.url") == None);
+		assert!(VariableMap::get(&variables, "dotted.key").as_deref() == Some("top"));
+		assert!(VariableMap::get(&serde_json::Value::from("name"), "name") == None);
+
+		let_assert!(Ok(expanded) = crate::substitute("$name:$port", &variables));
+		assert!(expanded == "app:8080");
+	}
 }
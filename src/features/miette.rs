@@ -0,0 +1,333 @@
+//! Support for reporting errors with [`miette`].
+//!
+//! With the `miette` feature enabled, [`Error`], [`ParseError`] and [`ExpandError`] implement [`Diagnostic`].
+//! The diagnostics have an error code, a label for the error location and help text for common mistakes.
+//! Errors in included templates are reported as related diagnostics of the include directive.
+//!
+//! The errors do not hold the source text themselves.
+//! Use [`WithSource`] to attach the named source text, so that the labels can be rendered in a report.
+//!
+//! # Example
+//! ```
+//! # use std::collections::HashMap;
+//! use subst::miette::WithSource;
+//!
+//! let source = "Hello ${name";
+//! let variables: HashMap<&str, &str> = HashMap::new();
+//! let error = subst::substitute(source, &variables).unwrap_err();
+//! let report = miette::Report::new(WithSource::new(error, "greeting.txt", source));
+//! # assert_eq!(report.to_string(), "Missing closing brace");
+//! ```
+
+use ::miette::{Diagnostic, LabeledSpan, NamedSource, SourceCode};
+use std::fmt::Display;
+
+use super::diagnostic::{code, help, label_message, ErrorRef};
+use crate::error::{ExpandError, IncludeErrorKind, ParseError};
+use crate::Error;
+
+/// An error together with the named source text that contains the error.
+///
+/// This allows reports to render the labels of the diagnostic in the source text.
+#[derive(Debug)]
+pub struct WithSource<E> {
+	error: E,
+	source: NamedSource<String>,
+}
+
+impl<E> WithSource<E> {
+	/// Attach the named source text to an error.
+	///
+	/// The source text must be the source text that contains the error.
+	#[inline]
+	pub fn new(error: E, name: impl AsRef<str>, source: impl Into<String>) -> Self {
+		Self {
+			error,
+			source: NamedSource::new(name, source.into()),
+		}
+	}
+
+	/// Get the wrapped error.
+	#[inline]
+	pub fn error(&self) -> &E {
+		&self.error
+	}
+
+	/// Get the name of the source text.
+	#[inline]
+	pub fn name(&self) -> &str {
+		self.source.name()
+	}
+
+	/// Consume `self` to get the wrapped error.
+	#[inline]
+	pub fn into_error(self) -> E {
+		self.error
+	}
+}
+
+impl<E: Display> Display for WithSource<E> {
+	#[inline]
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		self.error.fmt(f)
+	}
+}
+
+impl<E: std::error::Error> std::error::Error for WithSource<E> {}
+
+impl<E: Diagnostic> Diagnostic for WithSource<E> {
+	#[inline]
+	fn code<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+		self.error.code()
+	}
+
+	#[inline]
+	fn help<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+		self.error.help()
+	}
+
+	#[inline]
+	fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+		self.error.labels()
+	}
+
+	#[inline]
+	fn related<'a>(&'a self) -> Option<Box<dyn Iterator<Item = &'a dyn Diagnostic> + 'a>> {
+		self.error.related()
+	}
+
+	#[inline]
+	fn source_code(&self) -> Option<&dyn SourceCode> {
+		Some(&self.source)
+	}
+}
+
+impl Diagnostic for Error {
+	#[inline]
+	fn code<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+		Some(Box::new(code(self.into())))
+	}
+
+	#[inline]
+	fn help<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+		help(self.into()).map(|help| Box::new(help) as Box<dyn Display>)
+	}
+
+	#[inline]
+	fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+		Some(Box::new(std::iter::once(label(self.into()))))
+	}
+
+	#[inline]
+	fn related<'a>(&'a self) -> Option<Box<dyn Iterator<Item = &'a dyn Diagnostic> + 'a>> {
+		related(self.into())
+	}
+}
+
+impl Diagnostic for ParseError {
+	#[inline]
+	fn code<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+		Some(Box::new(code(self.into())))
+	}
+
+	#[inline]
+	fn help<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+		help(self.into()).map(|help| Box::new(help) as Box<dyn Display>)
+	}
+
+	#[inline]
+	fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+		Some(Box::new(std::iter::once(label(self.into()))))
+	}
+
+	#[inline]
+	fn related<'a>(&'a self) -> Option<Box<dyn Iterator<Item = &'a dyn Diagnostic> + 'a>> {
+		related(self.into())
+	}
+}
+
+impl Diagnostic for ExpandError {
+	#[inline]
+	fn code<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+		Some(Box::new(code(self.into())))
+	}
+
+	#[inline]
+	fn help<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+		help(self.into()).map(|help| Box::new(help) as Box<dyn Display>)
+	}
+
+	#[inline]
+	fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+		Some(Box::new(std::iter::once(label(self.into()))))
+	}
+
+	#[inline]
+	fn related<'a>(&'a self) -> Option<Box<dyn Iterator<Item = &'a dyn Diagnostic> + 'a>> {
+		related(self.into())
+	}
+}
+
+/// An error in an included template, reported as a related diagnostic of the include directive.
+///
+/// The location of the wrapped error is relative to the included template, which is not available here.
+/// So unlike the wrapped error, this diagnostic has no labels.
+#[derive(Debug)]
+#[repr(transparent)]
+struct Included<E>(E);
+
+impl<E> Included<E> {
+	fn new(error: &E) -> &Self {
+		// SAFETY: `Included<E>` is a `repr(transparent)` wrapper around `E`, so it has the same layout.
+		unsafe { &*(error as *const E as *const Self) }
+	}
+}
+
+impl<E: Display> Display for Included<E> {
+	#[inline]
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		self.0.fmt(f)
+	}
+}
+
+impl<E: std::error::Error> std::error::Error for Included<E> {}
+
+impl<E: Diagnostic> Diagnostic for Included<E> {
+	#[inline]
+	fn code<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+		self.0.code()
+	}
+
+	#[inline]
+	fn help<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+		self.0.help()
+	}
+
+	#[inline]
+	fn related<'a>(&'a self) -> Option<Box<dyn Iterator<Item = &'a dyn Diagnostic> + 'a>> {
+		self.0.related()
+	}
+}
+
+/// Get the label for the location of an error.
+fn label(error: ErrorRef) -> LabeledSpan {
+	let (start, len) = match error {
+		ErrorRef::InvalidEscapeSequence(e) => {
+			let char_len = e.character.map_or(0, |x| x.source_len());
+			(e.position, 1 + char_len)
+		},
+		ErrorRef::MissingVariableName(e) => (e.position, e.len),
+		ErrorRef::UnexpectedCharacter(e) => (e.position, e.character.source_len()),
+		ErrorRef::MissingClosingBrace(e) => (e.position, 1),
+		ErrorRef::NoSuchVariable(e) => (e.position, e.name.len()),
+		ErrorRef::NotPermitted(e) => (e.position, e.name.len()),
+		ErrorRef::Cycle(e) => (e.position, e.name.len()),
+		ErrorRef::Include(e) => (e.position, e.len),
+	};
+	LabeledSpan::new_with_span(Some(label_message(error)), start..start + len)
+}
+
+/// Get the error from an included template as related diagnostic.
+fn related<'a>(error: ErrorRef<'a>) -> Option<Box<dyn Iterator<Item = &'a dyn Diagnostic> + 'a>> {
+	let ErrorRef::Include(error) = error else {
+		return None;
+	};
+	let related: &dyn Diagnostic = match &error.kind {
+		IncludeErrorKind::Parse(e) => Included::new(&**e),
+		IncludeErrorKind::Expand(e) => Included::new(&**e),
+		_ => return None,
+	};
+	Some(Box::new(std::iter::once(related)))
+}
+
+#[cfg(test)]
+#[rustfmt::skip]
+mod test {
+	use std::collections::BTreeMap;
+
+	use super::*;
+	use assert2::{assert, let_assert};
+
+	fn render(error: impl Diagnostic + Send + Sync + 'static) -> String {
+		let mut output = String::new();
+		::miette::NarratableReportHandler::new()
+			.render_report(&mut output, &error)
+			.unwrap();
+		output
+	}
+
+	#[test]
+	fn test_diagnostic() {
+		let source = "Hello ${name}!";
+		let_assert!(Err(e) = crate::substitute(source, &BTreeMap::<String, String>::new()));
+		assert!(e.code().unwrap().to_string() == "subst::no_such_variable");
+		assert!(e.help().unwrap().to_string() == "set the variable `name`, or add a default value with `${name:default}`");
+		let_assert!(Some(labels) = e.labels());
+		let labels: Vec<_> = labels.collect();
+		assert!(labels.len() == 1);
+		assert!(labels[0].offset() == 8);
+		assert!(labels[0].len() == 4);
+		assert!(labels[0].label() == Some("variable is not set"));
+	}
+
+	#[test]
+	fn test_parse_error_diagnostic() {
+		let_assert!(Err(e) = crate::Template::from_str("Hello $!"));
+		assert!(e.code().unwrap().to_string() == "subst::missing_variable_name");
+		assert!(e.help().unwrap().to_string() == "a variable name must follow the `$`, so escape a literal `$` as `\\$`");
+	}
+
+	#[test]
+	fn test_with_source() {
+		let source = "first line\nsecond \\line";
+		let_assert!(Err(e) = crate::substitute(source, &BTreeMap::<String, String>::new()));
+		let report = render(WithSource::new(e, "example.txt", source));
+		assert!(report.contains("Invalid escape sequence: \\l"));
+		assert!(report.contains("Begin snippet for example.txt"));
+		assert!(report.contains("label at line 2, columns 8 to 9: invalid escape sequence"));
+	}
+
+	#[test]
+	fn test_include_related() {
+		use crate::include::MemoryResolver;
+
+		let resolver: MemoryResolver = [("inner", "Hello ${name}!")].into_iter().collect();
+		let source = "${@include inner}";
+		let template = crate::Template::from_str_with_includes(source, &resolver, None).unwrap();
+		let_assert!(Err(e) = template.expand(&BTreeMap::<String, String>::new()));
+		assert!(e.code().unwrap().to_string() == "subst::include");
+
+		let_assert!(Some(labels) = e.labels());
+		let labels: Vec<_> = labels.collect();
+		assert!(labels.len() == 1);
+		assert!(labels[0].offset() == 0);
+		assert!(labels[0].len() == source.len());
+
+		let_assert!(Some(related) = e.related());
+		let related: Vec<_> = related.collect();
+		assert!(related.len() == 1);
+		assert!(related[0].to_string() == "No such variable: $name");
+		assert!(related[0].code().unwrap().to_string() == "subst::no_such_variable");
+		assert!(related[0].labels().is_none());
+
+		let report = render(WithSource::new(e.clone(), "main.txt", source));
+		assert!(report.contains("No such variable: $name"));
+		assert!(report.contains("label at line 1, columns 1 to 17: included here"));
+	}
+
+	#[test]
+	fn test_include_parse_error_related() {
+		use crate::include::MemoryResolver;
+
+		let resolver: MemoryResolver = [("inner", "Hello $!")].into_iter().collect();
+		let_assert!(Err(e) = crate::Template::from_str_with_includes("${@include inner}", &resolver, None));
+		let_assert!(Some(related) = e.related());
+		let related: Vec<_> = related.collect();
+		assert!(related.len() == 1);
+		assert!(related[0].code().unwrap().to_string() == "subst::missing_variable_name");
+		assert!(related[0].labels().is_none());
+
+		let_assert!(Err(e) = crate::Template::from_str("Hello $!"));
+		assert!(e.related().is_none());
+	}
+}
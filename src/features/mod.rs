@@ -2,6 +2,10 @@
 #[cfg_attr(feature = "doc-cfg", doc(cfg(feature = "indexmap")))]
 mod indexmap;
 
+#[cfg(feature = "serde")]
+#[cfg_attr(feature = "doc-cfg", doc(cfg(feature = "serde")))]
+mod serde;
+
 #[cfg(feature = "json")]
 #[cfg_attr(feature = "doc-cfg", doc(cfg(feature = "json")))]
 pub mod json;
@@ -13,3 +17,14 @@ pub mod yaml;
 #[cfg(feature = "toml")]
 #[cfg_attr(feature = "doc-cfg", doc(cfg(feature = "toml")))]
 pub mod toml;
+
+#[cfg(any(feature = "codespan-reporting", feature = "miette"))]
+mod diagnostic;
+
+#[cfg(feature = "codespan-reporting")]
+#[cfg_attr(feature = "doc-cfg", doc(cfg(feature = "codespan-reporting")))]
+pub mod codespan;
+
+#[cfg(feature = "miette")]
+#[cfg_attr(feature = "doc-cfg", doc(cfg(feature = "miette")))]
+pub mod miette;
@@ -0,0 +1,124 @@
+use serde::de::{Deserialize, Deserializer, Error, SeqAccess, Visitor};
+use serde::ser::{Serialize, Serializer};
+
+use crate::{ByteTemplateBuf, TemplateBuf};
+
+/// Serialize the template as the original source string.
+impl Serialize for TemplateBuf {
+	#[inline]
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.serialize_str(self.as_template().source())
+	}
+}
+
+/// Deserialize and parse a template from a string.
+///
+/// Parse errors are reported as deserialization errors.
+impl<'de> Deserialize<'de> for TemplateBuf {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		let source = String::deserialize(deserializer)?;
+		Self::from_string(source).map_err(D::Error::custom)
+	}
+}
+
+/// Serialize the template as the original source bytes.
+impl Serialize for ByteTemplateBuf {
+	#[inline]
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.serialize_bytes(self.as_template().source())
+	}
+}
+
+/// Deserialize and parse a template from a string or a byte sequence.
+///
+/// Parse errors are reported as deserialization errors.
+impl<'de> Deserialize<'de> for ByteTemplateBuf {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		struct BytesVisitor;
+
+		impl<'de> Visitor<'de> for BytesVisitor {
+			type Value = Vec<u8>;
+
+			fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+				write!(f, "a string or byte sequence")
+			}
+
+			fn visit_str<E: Error>(self, value: &str) -> Result<Self::Value, E> {
+				Ok(value.as_bytes().into())
+			}
+
+			fn visit_string<E: Error>(self, value: String) -> Result<Self::Value, E> {
+				Ok(value.into_bytes())
+			}
+
+			fn visit_bytes<E: Error>(self, value: &[u8]) -> Result<Self::Value, E> {
+				Ok(value.into())
+			}
+
+			fn visit_byte_buf<E: Error>(self, value: Vec<u8>) -> Result<Self::Value, E> {
+				Ok(value)
+			}
+
+			fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+				let mut data = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+				while let Some(byte) = seq.next_element()? {
+					data.push(byte);
+				}
+				Ok(data)
+			}
+		}
+
+		let source = deserializer.deserialize_byte_buf(BytesVisitor)?;
+		Self::from_vec(source).map_err(D::Error::custom)
+	}
+}
+
+#[cfg(test)]
+#[rustfmt::skip]
+mod test {
+	use std::collections::HashMap;
+
+	use super::*;
+	use assert2::{assert, let_assert};
+
+	#[derive(Debug, serde::Deserialize, serde::Serialize)]
+	struct Config {
+		log_path: TemplateBuf,
+	}
+
+	#[derive(Debug, serde::Deserialize, serde::Serialize)]
+	struct ByteConfig {
+		log_path: ByteTemplateBuf,
+	}
+
+	#[test]
+	fn test_deserialize_template_buf() {
+		let_assert!(Ok(config) = serde_json::from_str::<Config>(r#"{ "log_path": "${LOG_DIR:/var/log}/app.log" }"#));
+		let variables: HashMap<&str, &str> = [("LOG_DIR", "/tmp")].into_iter().collect();
+		assert!(config.log_path.expand(&variables).as_deref() == Ok("/tmp/app.log"));
+
+		let_assert!(Ok(json) = serde_json::to_string(&config));
+		assert!(json == r#"{"log_path":"${LOG_DIR:/var/log}/app.log"}"#);
+	}
+
+	#[test]
+	fn test_deserialize_template_buf_parse_error() {
+		let_assert!(Err(e) = serde_json::from_str::<Config>(r#"{ "log_path": "${LOG_DIR/app.log" }"#));
+		assert!(e.to_string().starts_with("Unexpected character: '/', expected a closing brace ('}'), a colon (':') or a variable name character"));
+	}
+
+	#[test]
+	fn test_deserialize_byte_template_buf() {
+		let variables: HashMap<&str, &[u8]> = [("LOG_DIR", &b"/tmp"[..])].into_iter().collect();
+
+		let_assert!(Ok(config) = serde_json::from_str::<ByteConfig>(r#"{ "log_path": "$LOG_DIR/app.log" }"#));
+		assert!(config.log_path.expand(&variables).as_deref() == Ok(&b"/tmp/app.log"[..]));
+
+		let_assert!(Ok(json) = serde_json::to_string(&config));
+		let_assert!(Ok(config) = serde_json::from_str::<ByteConfig>(&json));
+		assert!(config.log_path.as_template().source() == b"$LOG_DIR/app.log");
+
+		let_assert!(Err(e) = serde_json::from_str::<ByteConfig>(r#"{ "log_path": [36] }"#));
+		assert!(e.to_string().starts_with("Missing variable name"));
+	}
+}
@@ -1,7 +1,10 @@
 //! Support for variable substitution in TOML data.
 
-use serde::de::DeserializeOwned;
+use std::borrow::Cow;
 
+use serde::de::{DeserializeOwned, DeserializeSeed, IgnoredAny, MapAccess, SeqAccess, Visitor};
+
+use crate::error::{DocumentError, DocumentPath, LineColumn, PathSegment};
 use crate::VariableMap;
 
 /// Parse a struct from TOML data, after performing variable substitution on string values.
@@ -28,18 +31,29 @@ where
 	M::Value: AsRef<str>,
 {
 	let mut value: toml::Value = toml::from_str(data)?;
-	substitute_string_values(&mut value, variables)?;
+	substitute_string_values(&mut value, variables).map_err(|mut e| {
+		e.line_column = find_value(data, &e.path.segments).map(|span| LineColumn::from_position(data, span.start));
+		e
+	})?;
 	Ok(T::deserialize(value)?)
 }
 
 /// Perform variable substitution on string values of a TOML value.
-pub fn substitute_string_values<'a, M>(value: &mut toml::Value, variables: &'a M) -> Result<(), crate::Error>
+///
+/// If substitution fails, the returned error contains the path of the string value that caused the error.
+pub fn substitute_string_values<'a, M>(value: &mut toml::Value, variables: &'a M) -> Result<(), DocumentError>
 where
 	M: VariableMap<'a> + ?Sized,
 	M::Value: AsRef<str>,
 {
-	visit_string_values(value, |value| {
-		*value = crate::substitute(value.as_str(), variables)?;
+	visit_string_values(value, &mut Vec::new(), |value, path| {
+		*value = crate::substitute(value.as_str(), variables).map_err(|error| DocumentError {
+			path: DocumentPath {
+				segments: path.to_vec(),
+			},
+			line_column: None,
+			error: Box::new(error),
+		})?;
 		Ok(())
 	})
 }
@@ -53,8 +67,8 @@ pub enum Error {
 	/// An error occurred while parsing TOML.
 	Toml(toml::de::Error),
 
-	/// An error occurred while performing variable substitution.
-	Subst(crate::Error),
+	/// An error occurred while performing variable substitution on a string value.
+	Subst(DocumentError),
 }
 
 impl From<std::str::Utf8Error> for Error {
@@ -71,13 +85,40 @@ impl From<toml::de::Error> for Error {
 	}
 }
 
-impl From<crate::Error> for Error {
+impl From<DocumentError> for Error {
 	#[inline]
-	fn from(other: crate::Error) -> Self {
+	fn from(other: DocumentError) -> Self {
 		Self::Subst(other)
 	}
 }
 
+impl Error {
+	/// Get the path of the string value that caused a substitution error.
+	///
+	/// Returns `None` if the error is not a substitution error.
+	#[inline]
+	pub fn path(&self) -> Option<&DocumentPath> {
+		match self {
+			Self::Subst(e) => Some(&e.path),
+			_ => None,
+		}
+	}
+
+	/// Get the line and column in the original document of the string value that caused a substitution error.
+	///
+	/// This is the location of the opening quote of the string value, not of the failed substitution inside the string.
+	/// The position of the failed substitution within the string value is available from the wrapped error.
+	///
+	/// Returns `None` if the error is not a substitution error, or if the location is not known.
+	#[inline]
+	pub fn line_column(&self) -> Option<LineColumn> {
+		match self {
+			Self::Subst(e) => e.line_column,
+			_ => None,
+		}
+	}
+}
+
 impl std::error::Error for Error {}
 
 impl std::fmt::Display for Error {
@@ -91,32 +132,210 @@ impl std::fmt::Display for Error {
 	}
 }
 
+/// Look up variables in the top-level table of a TOML value.
+///
+/// A variable is looked up as a top-level key first.
+/// If there is no such key, the variable name is interpreted as a dotted path into the document,
+/// where each segment is a table key or an array index, like `servers.0.url`.
+///
+/// Strings, numbers, booleans and datetimes are rendered as strings.
+/// Arrays and tables are treated as missing variables.
+///
+/// Dotted paths are not valid variable names in a template,
+/// but they can be used as the key of a scheme-prefixed variable with a [`Router`][crate::map::Router].
+///
+/// # Example
+/// ```
+/// # use subst::map::Router;
+/// let vars: toml::Value = toml::from_str(r#"
+///   name = "app"
+///   port = 8080
+///   [[servers]]
+///   url = "http://localhost"
+/// "#).unwrap();
+/// assert_eq!(subst::substitute("$name:$port", &vars).unwrap(), "app:8080");
+///
+/// let variables: Router = Router::new().route("vars", vars);
+/// assert_eq!(subst::substitute("${@vars:servers.0.url}", &variables).unwrap(), "http://localhost");
+/// ```
+impl<'a> VariableMap<'a> for toml::Value {
+	type Value = Cow<'a, str>;
+
+	#[inline]
+	fn get(&'a self, key: &str) -> Option<Self::Value> {
+		VariableMap::get(self.as_table()?, key)
+	}
+}
+
+/// Look up variables in a TOML table.
+///
+/// Variables are resolved the same as for a [`toml::Value`].
+impl<'a> VariableMap<'a> for toml::Table {
+	type Value = Cow<'a, str>;
+
+	fn get(&'a self, key: &str) -> Option<Self::Value> {
+		scalar_to_string(find_variable(self, key)?)
+	}
+}
+
 /// Recursively apply a function to all string values in a TOML value.
-fn visit_string_values<F, E>(value: &mut toml::Value, fun: F) -> Result<(), E>
+///
+/// The `path` is extended with the path of each value while it is being visited.
+fn visit_string_values<F, E>(value: &mut toml::Value, path: &mut Vec<PathSegment>, fun: F) -> Result<(), E>
 where
-	F: Copy + Fn(&mut String) -> Result<(), E>,
+	F: Copy + Fn(&mut String, &[PathSegment]) -> Result<(), E>,
 {
 	match value {
 		toml::Value::Boolean(_) => Ok(()),
 		toml::Value::Integer(_) => Ok(()),
 		toml::Value::Float(_) => Ok(()),
 		toml::Value::Datetime(_) => Ok(()),
-		toml::Value::String(val) => fun(val),
+		toml::Value::String(val) => fun(val, path),
 		toml::Value::Array(seq) => {
-			for value in seq {
-				visit_string_values(value, fun)?;
+			for (index, value) in seq.iter_mut().enumerate() {
+				path.push(PathSegment::Index(index));
+				visit_string_values(value, path, fun)?;
+				path.pop();
 			}
 			Ok(())
 		},
 		toml::Value::Table(map) => {
-			for (_key, value) in map.iter_mut() {
-				visit_string_values(value, fun)?;
+			for (key, value) in map.iter_mut() {
+				path.push(PathSegment::Key(key.clone()));
+				visit_string_values(value, path, fun)?;
+				path.pop();
 			}
 			Ok(())
 		},
 	}
 }
 
+/// Find the value of a variable by top-level key or dotted path.
+fn find_variable<'a>(table: &'a toml::Table, key: &str) -> Option<&'a toml::Value> {
+	if let Some(value) = table.get(key) {
+		return Some(value);
+	}
+	let (first, rest) = key.split_once('.')?;
+	rest.split('.')
+		.try_fold(table.get(first)?, |value, segment| match value {
+			toml::Value::Table(table) => table.get(segment),
+			toml::Value::Array(seq) => seq.get(segment.parse::<usize>().ok()?),
+			_ => None,
+		})
+}
+
+/// Render a scalar TOML value as string.
+///
+/// Returns `None` for arrays and tables.
+fn scalar_to_string(value: &toml::Value) -> Option<Cow<'_, str>> {
+	match value {
+		toml::Value::Boolean(value) => Some(Cow::Owned(value.to_string())),
+		toml::Value::Integer(value) => Some(Cow::Owned(value.to_string())),
+		toml::Value::Float(value) => Some(Cow::Owned(value.to_string())),
+		toml::Value::Datetime(value) => Some(Cow::Owned(value.to_string())),
+		toml::Value::String(value) => Some(Cow::Borrowed(value)),
+		toml::Value::Array(_) => None,
+		toml::Value::Table(_) => None,
+	}
+}
+
+/// Find the byte range of the value at the given path in TOML source text.
+fn find_value(data: &str, path: &[PathSegment]) -> Option<std::ops::Range<usize>> {
+	if path.is_empty() {
+		return None;
+	}
+	FindValue { path }
+		.deserialize(toml::Deserializer::new(data))
+		.ok()
+		.flatten()
+}
+
+/// Deserializer seed that finds the span of a value in a TOML document, without keeping the document in memory.
+struct FindValue<'a> {
+	/// The path of the value, relative to the value being deserialized. Must not be empty.
+	path: &'a [PathSegment],
+}
+
+impl<'a> FindValue<'a> {
+	/// Determine what to do with the value for a path segment.
+	///
+	/// Returns `None` if the value should be skipped,
+	/// `Some(None)` if the value is the one being searched for,
+	/// or `Some(Some(seed))` if the value is a parent of the value being searched for.
+	fn step(&self, segment: &PathSegment) -> Option<Option<FindValue<'a>>> {
+		let (first, rest) = self.path.split_first()?;
+		if first != segment {
+			None
+		} else if rest.is_empty() {
+			Some(None)
+		} else {
+			Some(Some(FindValue { path: rest }))
+		}
+	}
+}
+
+impl<'de> DeserializeSeed<'de> for FindValue<'_> {
+	type Value = Option<std::ops::Range<usize>>;
+
+	fn deserialize<D: serde::Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+		deserializer.deserialize_any(self)
+	}
+}
+
+impl<'de> Visitor<'de> for FindValue<'_> {
+	type Value = Option<std::ops::Range<usize>>;
+
+	fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(f, "a TOML table or array")
+	}
+
+	fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+		let mut found = None;
+		while let Some(key) = map.next_key::<String>()? {
+			match self.step(&PathSegment::Key(key)) {
+				None => drop(map.next_value::<IgnoredAny>()?),
+				Some(None) => found = Some(map.next_value::<toml::Spanned<IgnoredAny>>()?.span()),
+				Some(Some(seed)) => found = map.next_value_seed(seed)?,
+			}
+		}
+		Ok(found)
+	}
+
+	fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+		let mut found = None;
+		for index in 0.. {
+			let more = match self.step(&PathSegment::Index(index)) {
+				None => seq.next_element::<IgnoredAny>()?.is_some(),
+				Some(None) => seq
+					.next_element::<toml::Spanned<IgnoredAny>>()?
+					.map(|value| found = Some(value.span()))
+					.is_some(),
+				Some(Some(seed)) => seq.next_element_seed(seed)?.map(|value| found = value).is_some(),
+			};
+			if !more {
+				break;
+			}
+		}
+		Ok(found)
+	}
+
+	fn visit_bool<E>(self, _value: bool) -> Result<Self::Value, E> {
+		Ok(None)
+	}
+
+	fn visit_i64<E>(self, _value: i64) -> Result<Self::Value, E> {
+		Ok(None)
+	}
+
+	fn visit_f64<E>(self, _value: f64) -> Result<Self::Value, E> {
+		Ok(None)
+	}
+
+	fn visit_str<E>(self, _value: &str) -> Result<Self::Value, E> {
+		Ok(None)
+	}
+}
+
 #[cfg(test)]
 #[rustfmt::skip]
 mod test {
@@ -225,4 +444,63 @@ mod test {
 		assert!(parsed.bar == "aap");
 		assert!(parsed.baz == "noot/with/stuff");
 	}
+
+	#[test]
+	fn test_error_path_and_line_column() {
+		let mut variables = HashMap::new();
+		variables.insert("DB_HOST", "localhost");
+		let source = concat!(
+			"[database]\n",
+			"url = \"postgres://${DB_HOST}/app\"\n",
+			"\n",
+			"[[servers]]\n",
+			"url = \"http://a\"\n",
+			"[[servers]]\n",
+			"url = \"http://b\"\n",
+			"[[servers]]\n",
+			"name = \"c\"\n",
+			"url = \"http://${HOST}/\"\n",
+		);
+		let_assert!(Err(e) = from_str::<toml::Value, _>(source, &variables));
+		let_assert!(Some(path) = e.path());
+		assert!(path.to_string() == "servers[2].url");
+		assert!(e.line_column() == Some(LineColumn { line: 10, column: 7 }));
+		assert!(e.to_string() == "servers[2].url (line 10, column 7): No such variable: $HOST");
+
+		let source = "paths = [\"/a\", { \"my dir\" = \"$DIR\" }]";
+		let_assert!(Err(e) = from_str::<toml::Value, _>(source, &variables));
+		assert!(e.path().unwrap().to_string() == "paths[1].\"my dir\"");
+		assert!(e.line_column() == Some(LineColumn { line: 1, column: 29 }));
+	}
+
+	#[test]
+	fn test_value_as_variable_map() {
+		let_assert!(Ok(variables) = toml::from_str::<toml::Value>(r#"
+			name = "app"
+			port = 8080
+			debug = false
+			ratio = 0.5
+			date = 2024-01-02
+			"dotted.key" = "top"
+
+			[[servers]]
+			url = "http://localhost"
+
+			[dotted]
+			key = "nested"
+		"#));
+		assert!(VariableMap::get(&variables, "name").as_deref() == Some("app"));
+		assert!(VariableMap::get(&variables, "port").as_deref() == Some("8080"));
+		assert!(VariableMap::get(&variables, "debug").as_deref() == Some("false"));
+		assert!(VariableMap::get(&variables, "ratio").as_deref() == Some("0.5"));
+		assert!(VariableMap::get(&variables, "date").as_deref() == Some("2024-01-02"));
+		assert!(VariableMap::get(&variables, "servers") == None);
+		assert!(VariableMap::get(&variables, "servers.0.url").as_deref() == Some("http://localhost"));
+		assert!(VariableMap::get(&variables, "servers.1.url") == None);
+		assert!(VariableMap::get(&variables, "dotted.key").as_deref() == Some("top"));
+
+		let_assert!(Some(table) = variables.as_table());
+		let_assert!(Ok(expanded) = crate::substitute("$name:$port", table));
+		assert!(expanded == "app:8080");
+	}
 }
@@ -1,7 +1,10 @@
 //! Support for variable substitution in YAML data.
 
+use std::borrow::Cow;
+
 use serde::de::DeserializeOwned;
 
+use crate::error::{DocumentError, DocumentPath, LineColumn, PathSegment};
 use crate::VariableMap;
 
 /// Parse a struct from YAML data, after performing variable substitution on string values.
@@ -35,13 +38,21 @@ where
 }
 
 /// Perform variable substitution on string values of a YAML value.
-pub fn substitute_string_values<'a, M>(value: &mut serde_yaml::Value, variables: &'a M) -> Result<(), crate::Error>
+///
+/// If substitution fails, the returned error contains the path of the string value that caused the error.
+pub fn substitute_string_values<'a, M>(value: &mut serde_yaml::Value, variables: &'a M) -> Result<(), DocumentError>
 where
 	M: VariableMap<'a> + ?Sized,
 	M::Value: AsRef<str>,
 {
-	visit_string_values(value, |value| {
-		*value = crate::substitute(value.as_str(), variables)?;
+	visit_string_values(value, &mut Vec::new(), |value, path| {
+		*value = crate::substitute(value.as_str(), variables).map_err(|error| DocumentError {
+			path: DocumentPath {
+				segments: path.to_vec(),
+			},
+			line_column: None,
+			error: Box::new(error),
+		})?;
 		Ok(())
 	})
 }
@@ -52,8 +63,8 @@ pub enum Error {
 	/// An error occurred while parsing YAML.
 	Yaml(serde_yaml::Error),
 
-	/// An error occurred while performing variable substitution.
-	Subst(crate::Error),
+	/// An error occurred while performing variable substitution on a string value.
+	Subst(DocumentError),
 }
 
 impl From<serde_yaml::Error> for Error {
@@ -63,13 +74,38 @@ impl From<serde_yaml::Error> for Error {
 	}
 }
 
-impl From<crate::Error> for Error {
+impl From<DocumentError> for Error {
 	#[inline]
-	fn from(other: crate::Error) -> Self {
+	fn from(other: DocumentError) -> Self {
 		Self::Subst(other)
 	}
 }
 
+impl Error {
+	/// Get the path of the string value that caused a substitution error.
+	///
+	/// Returns `None` if the error is not a substitution error.
+	#[inline]
+	pub fn path(&self) -> Option<&DocumentPath> {
+		match self {
+			Self::Subst(e) => Some(&e.path),
+			_ => None,
+		}
+	}
+
+	/// Get the line and column in the original document of the string value that caused a substitution error.
+	///
+	/// This always returns `None` for now, because the YAML parser does not report the location of values.
+	/// Use [`Self::path()`] to find the string value that caused the error.
+	#[inline]
+	pub fn line_column(&self) -> Option<LineColumn> {
+		match self {
+			Self::Subst(e) => e.line_column,
+			_ => None,
+		}
+	}
+}
+
 impl std::error::Error for Error {}
 
 impl std::fmt::Display for Error {
@@ -82,32 +118,136 @@ impl std::fmt::Display for Error {
 	}
 }
 
+/// Look up variables in the top-level mapping of a YAML value.
+///
+/// A variable is looked up as a top-level key first.
+/// If there is no such key, the variable name is interpreted as a dotted path into the document,
+/// where each segment is a mapping key or a sequence index, like `servers.0.url`.
+/// Only string keys can be used for look-up.
+///
+/// Strings, numbers and booleans are rendered as strings, and tags are ignored.
+/// Null values, sequences and mappings are treated as missing variables.
+///
+/// Dotted paths are not valid variable names in a template,
+/// but they can be used as the key of a scheme-prefixed variable with a [`Router`][crate::map::Router].
+///
+/// # Example
+/// ```
+/// # use subst::map::Router;
+/// let vars: serde_yaml::Value = serde_yaml::from_str("
+///   name: app
+///   port: 8080
+///   servers:
+///     - url: http://localhost
+/// ").unwrap();
+/// assert_eq!(subst::substitute("$name:$port", &vars).unwrap(), "app:8080");
+///
+/// let variables: Router = Router::new().route("vars", vars);
+/// assert_eq!(subst::substitute("${@vars:servers.0.url}", &variables).unwrap(), "http://localhost");
+/// ```
+impl<'a> VariableMap<'a> for serde_yaml::Value {
+	type Value = Cow<'a, str>;
+
+	#[inline]
+	fn get(&'a self, key: &str) -> Option<Self::Value> {
+		match untag(self) {
+			serde_yaml::Value::Mapping(map) => VariableMap::get(map, key),
+			_ => None,
+		}
+	}
+}
+
+/// Look up variables in a YAML mapping.
+///
+/// Variables are resolved the same as for a [`serde_yaml::Value`].
+impl<'a> VariableMap<'a> for serde_yaml::Mapping {
+	type Value = Cow<'a, str>;
+
+	fn get(&'a self, key: &str) -> Option<Self::Value> {
+		scalar_to_string(find_variable(self, key)?)
+	}
+}
+
 /// Recursively apply a function to all string values in a YAML value.
-fn visit_string_values<F, E>(value: &mut serde_yaml::Value, fun: F) -> Result<(), E>
+///
+/// The `path` is extended with the path of each value while it is being visited.
+fn visit_string_values<F, E>(value: &mut serde_yaml::Value, path: &mut Vec<PathSegment>, fun: F) -> Result<(), E>
 where
-	F: Copy + Fn(&mut String) -> Result<(), E>,
+	F: Copy + Fn(&mut String, &[PathSegment]) -> Result<(), E>,
 {
 	match value {
 		serde_yaml::Value::Null => Ok(()),
 		serde_yaml::Value::Bool(_) => Ok(()),
 		serde_yaml::Value::Number(_) => Ok(()),
-		serde_yaml::Value::String(val) => fun(val),
-		serde_yaml::Value::Tagged(tagged) => visit_string_values(&mut tagged.value, fun),
+		serde_yaml::Value::String(val) => fun(val, path),
+		serde_yaml::Value::Tagged(tagged) => visit_string_values(&mut tagged.value, path, fun),
 		serde_yaml::Value::Sequence(seq) => {
-			for value in seq {
-				visit_string_values(value, fun)?;
+			for (index, value) in seq.iter_mut().enumerate() {
+				path.push(PathSegment::Index(index));
+				visit_string_values(value, path, fun)?;
+				path.pop();
 			}
 			Ok(())
 		},
 		serde_yaml::Value::Mapping(map) => {
-			for value in map.values_mut() {
-				visit_string_values(value, fun)?;
+			for (key, value) in map.iter_mut() {
+				path.push(PathSegment::Key(key_to_string(key)));
+				visit_string_values(value, path, fun)?;
+				path.pop();
 			}
 			Ok(())
 		},
 	}
 }
 
+/// Find the value of a variable by top-level key or dotted path.
+fn find_variable<'a>(map: &'a serde_yaml::Mapping, key: &str) -> Option<&'a serde_yaml::Value> {
+	if let Some(value) = map.get(key) {
+		return Some(value);
+	}
+	let (first, rest) = key.split_once('.')?;
+	rest.split('.')
+		.try_fold(map.get(first)?, |value, segment| match untag(value) {
+			serde_yaml::Value::Mapping(map) => map.get(segment),
+			serde_yaml::Value::Sequence(seq) => seq.get(segment.parse::<usize>().ok()?),
+			_ => None,
+		})
+}
+
+/// Get the value inside any tags.
+fn untag(mut value: &serde_yaml::Value) -> &serde_yaml::Value {
+	while let serde_yaml::Value::Tagged(tagged) = value {
+		value = &tagged.value;
+	}
+	value
+}
+
+/// Render a scalar YAML value as string.
+///
+/// Returns `None` for null values, sequences and mappings.
+fn scalar_to_string(value: &serde_yaml::Value) -> Option<Cow<'_, str>> {
+	match value {
+		serde_yaml::Value::Null => None,
+		serde_yaml::Value::Bool(value) => Some(Cow::Owned(value.to_string())),
+		serde_yaml::Value::Number(value) => Some(Cow::Owned(value.to_string())),
+		serde_yaml::Value::String(value) => Some(Cow::Borrowed(value)),
+		serde_yaml::Value::Tagged(tagged) => scalar_to_string(&tagged.value),
+		serde_yaml::Value::Sequence(_) => None,
+		serde_yaml::Value::Mapping(_) => None,
+	}
+}
+
+/// Format a YAML mapping key for use in a [`DocumentPath`].
+fn key_to_string(key: &serde_yaml::Value) -> String {
+	match key {
+		serde_yaml::Value::String(key) => key.clone(),
+		serde_yaml::Value::Bool(key) => key.to_string(),
+		serde_yaml::Value::Number(key) => key.to_string(),
+		serde_yaml::Value::Null => "null".into(),
+		key => serde_yaml::to_string(key).map_or_else(|_| "?".into(), |key| key.trim_end().into()),
+	}
+}
+
 #[cfg(test)]
 #[rustfmt::skip]
 mod test {
@@ -241,4 +381,51 @@ mod test {
 		assert!(parsed.bar == "aap");
 		assert!(parsed.baz == "noot/with/stuff");
 	}
+
+	#[test]
+	fn test_error_path() {
+		let variables: HashMap<&str, &str> = HashMap::new();
+		let source = concat!(
+			"servers:\n",
+			"  - url: a\n",
+			"  - url: http://$HOST/\n",
+		);
+		let_assert!(Err(e) = from_str::<serde_yaml::Value, _>(source, &variables));
+		assert!(e.path().unwrap().to_string() == "servers[1].url");
+		assert!(e.line_column() == None);
+		assert!(e.to_string() == "servers[1].url: No such variable: $HOST");
+
+		let_assert!(Err(e) = from_str::<serde_yaml::Value, _>("1: $HOST", &variables));
+		assert!(e.path().unwrap().to_string() == "1");
+	}
+
+	#[test]
+	fn test_value_as_variable_map() {
+		let source = concat!(
+			"name: app\n",
+			"port: 8080\n",
+			"debug: false\n",
+			"tagged: !Secret hunter2\n",
+			"none: ~\n",
+			"dotted.key: top\n",
+			"servers:\n",
+			"  - !Server\n",
+			"    url: http://localhost\n",
+			"dotted:\n",
+			"  key: nested\n",
+		);
+		let_assert!(Ok(variables) = serde_yaml::from_str::<serde_yaml::Value>(source));
+		assert!(VariableMap::get(&variables, "name").as_deref() == Some("app"));
+		assert!(VariableMap::get(&variables, "port").as_deref() == Some("8080"));
+		assert!(VariableMap::get(&variables, "debug").as_deref() == Some("false"));
+		assert!(VariableMap::get(&variables, "tagged").as_deref() == Some("hunter2"));
+		assert!(VariableMap::get(&variables, "none") == None);
+		assert!(VariableMap::get(&variables, "servers") == None);
+		assert!(VariableMap::get(&variables, "servers.0.url").as_deref() == Some("http://localhost"));
+		assert!(VariableMap::get(&variables, "servers.1.url") == None);
+		assert!(VariableMap::get(&variables, "dotted.key").as_deref() == Some("top"));
+
+		let_assert!(Ok(expanded) = crate::substitute("$name:$port", &variables));
+		assert!(expanded == "app:8080");
+	}
 }
@@ -0,0 +1,305 @@
+//! Support for including other templates with `${@include path}`.
+//!
+//! Include directives are resolved when a template is parsed.
+//! To enable them, parse the template with one of the `*_with_includes()` constructors,
+//! such as [`Template::from_str_with_includes()`][crate::Template::from_str_with_includes],
+//! and pass an [`IncludeResolver`] to load the included templates.
+//!
+//! The included template is parsed as part of the including template,
+//! so any variables in the included template are expanded with the same variable map.
+//!
+//! The constructors also take the ID of the template itself, if it has one, like the path of the file it was read from.
+//! It must be the same ID that the resolver would give the template.
+//! Then a template that includes the top-level template again is reported as an include cycle,
+//! without loading the top-level template a second time.
+//! The ID is also passed to the resolver as the parent of the include directives in the top-level template.
+//!
+//! # Example
+//! ```
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! # use std::collections::HashMap;
+//! use subst::include::MemoryResolver;
+//!
+//! let mut resolver = MemoryResolver::new();
+//! resolver.insert("logging", "level = \"${LOG_LEVEL:info}\"");
+//!
+//! let template = subst::Template::from_str_with_includes("[logging]\n${@include logging}\n", &resolver, None)?;
+//! let variables: HashMap<&str, &str> = HashMap::new();
+//! assert_eq!(template.expand(&variables)?, "[logging]\nlevel = \"info\"\n");
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Trait for types that can load included templates.
+pub trait IncludeResolver {
+	/// Load the template for an include directive.
+	///
+	/// The `path` is the path as written in the include directive.
+	/// The `parent` is the [`id`][IncludedSource::id] of the template that contains the directive.
+	/// For directives in the top-level template, it is the ID given when parsing the template, if any.
+	fn resolve(&self, path: &str, parent: Option<&str>) -> std::io::Result<IncludedSource>;
+}
+
+/// The source of an included template, as returned by an [`IncludeResolver`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IncludedSource {
+	/// A unique identifier for the included template.
+	///
+	/// The identifier is used to detect include cycles and to report the include chain in errors.
+	/// It is also passed as `parent` to the resolver for include directives inside the included template.
+	pub id: String,
+
+	/// The source text of the included template.
+	pub source: String,
+}
+
+impl<T> IncludeResolver for &'_ T
+where
+	T: ?Sized + IncludeResolver,
+{
+	#[inline]
+	fn resolve(&self, path: &str, parent: Option<&str>) -> std::io::Result<IncludedSource> {
+		T::resolve(self, path, parent)
+	}
+}
+
+/// Resolve includes by reading files from the file system.
+///
+/// Include paths are resolved relative to the directory of the including file.
+/// Include directives in a top-level template without an ID are resolved relative to the base directory.
+///
+/// The ID of an included file is its canonical path, as given by [`std::fs::canonicalize()`].
+/// Use the canonical path as the ID of a top-level template that was read from a file, to detect when it is included again.
+#[derive(Debug, Clone)]
+pub struct FileResolver {
+	base: PathBuf,
+}
+
+impl FileResolver {
+	/// Create a new resolver that resolves top-level includes relative to `base`.
+	#[inline]
+	pub fn new(base: impl Into<PathBuf>) -> Self {
+		Self { base: base.into() }
+	}
+
+	/// Get the base directory for top-level includes.
+	#[inline]
+	pub fn base(&self) -> &Path {
+		&self.base
+	}
+}
+
+impl IncludeResolver for FileResolver {
+	fn resolve(&self, path: &str, parent: Option<&str>) -> std::io::Result<IncludedSource> {
+		let dir = match parent {
+			Some(parent) => Path::new(parent).parent().unwrap_or(Path::new("")),
+			None => &self.base,
+		};
+		// Canonicalize the path, so that different spellings of the same file are detected as include cycles.
+		let path = std::fs::canonicalize(dir.join(path))?;
+		let source = std::fs::read_to_string(&path)?;
+		Ok(IncludedSource {
+			id: path.to_string_lossy().into_owned(),
+			source,
+		})
+	}
+}
+
+/// Resolve includes from templates stored in memory.
+///
+/// The include path must match the name of a template exactly.
+/// The name is also used as the template ID.
+///
+/// This is mainly useful for tests.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryResolver {
+	templates: BTreeMap<String, String>,
+}
+
+impl MemoryResolver {
+	/// Create a new resolver without any templates.
+	#[inline]
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Add a template to the resolver.
+	///
+	/// If a template with the same name was already present, it is replaced.
+	#[inline]
+	pub fn insert(&mut self, name: impl Into<String>, source: impl Into<String>) -> &mut Self {
+		self.templates.insert(name.into(), source.into());
+		self
+	}
+}
+
+impl<K, V> FromIterator<(K, V)> for MemoryResolver
+where
+	K: Into<String>,
+	V: Into<String>,
+{
+	fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+		let templates = iter.into_iter().map(|(k, v)| (k.into(), v.into())).collect();
+		Self { templates }
+	}
+}
+
+impl IncludeResolver for MemoryResolver {
+	fn resolve(&self, path: &str, _parent: Option<&str>) -> std::io::Result<IncludedSource> {
+		let source = self
+			.templates
+			.get(path)
+			.ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "no such template"))?;
+		Ok(IncludedSource {
+			id: path.to_owned(),
+			source: source.clone(),
+		})
+	}
+}
+
+#[cfg(test)]
+#[rustfmt::skip]
+mod test {
+	use super::*;
+	use crate::error::{IncludeErrorKind, ParseError};
+	use crate::test_util::TempDir;
+	use crate::{Error, Template};
+	use assert2::{assert, check, let_assert};
+	use std::collections::BTreeMap;
+
+	#[test]
+	fn test_include() {
+		let resolver: MemoryResolver = [
+			("tls", "cert = \"${TLS_CERT}\"\nkey = \"${TLS_KEY:/etc/key.pem}\""),
+			("server", "[server]\n${@include tls}"),
+		].into_iter().collect();
+
+		let mut variables = BTreeMap::new();
+		variables.insert("TLS_CERT", "/etc/cert.pem");
+
+		let_assert!(Ok(template) = Template::from_str_with_includes("${@include server}\n", &resolver, None));
+		check!(let Ok("[server]\ncert = \"/etc/cert.pem\"\nkey = \"/etc/key.pem\"\n") = template.expand(&variables).as_deref());
+
+		let_assert!(Ok(template) = Template::from_str_with_includes("${NAME:${@include  tls }}", &resolver, None));
+		check!(let Ok("cert = \"/etc/cert.pem\"\nkey = \"/etc/key.pem\"") = template.expand(&variables).as_deref());
+	}
+
+	#[test]
+	fn test_include_not_supported() {
+		let source = "Hello ${@include world}!";
+		let_assert!(Err(ParseError::Include(e)) = Template::from_str(source));
+		assert!(e.kind == IncludeErrorKind::NotSupported);
+		assert!(e.to_string() == "Include directives are not supported here");
+		#[rustfmt::skip]
+		assert!(Error::from(ParseError::Include(e)).source_highlighting(source) == concat!(
+				"  Hello ${@include world}!\n",
+				"        ^^^^^^^^^^^^^^^^^\n",
+		));
+	}
+
+	#[test]
+	fn test_include_missing_path() {
+		let resolver = MemoryResolver::new();
+		let_assert!(Err(ParseError::Include(e)) = Template::from_str_with_includes("${@include }", &resolver, None));
+		assert!(e.kind == IncludeErrorKind::MissingPath);
+		assert!(e.to_string() == "Missing include path");
+
+		let_assert!(Err(ParseError::MissingClosingBrace(e)) = Template::from_str_with_includes("${@include foo", &resolver, None));
+		assert!(e.position == 1);
+	}
+
+	#[test]
+	fn test_include_not_found() {
+		let resolver: MemoryResolver = [("a", "${@include b}")].into_iter().collect();
+		let_assert!(Err(ParseError::Include(e)) = Template::from_str_with_includes("x ${@include a}", &resolver, None));
+		assert!(e.position == 2);
+		assert!(e.len == 13);
+		assert!(e.chain == ["a", "b"]);
+		assert!(e.to_string() == "Failed to include template \"b\" (included via \"a\"): no such template");
+	}
+
+	#[test]
+	fn test_include_cycle() {
+		let resolver: MemoryResolver = [
+			("a", "${@include b}"),
+			("b", "${@include c}"),
+			("c", "${@include a}"),
+		].into_iter().collect();
+		let_assert!(Err(ParseError::Include(e)) = Template::from_str_with_includes("${@include a}", &resolver, None));
+		assert!(e.kind == IncludeErrorKind::Cycle);
+		assert!(e.chain == ["a", "b", "c", "a"]);
+		assert!(e.to_string() == "Include cycle: \"a\" -> \"b\" -> \"c\" -> \"a\"");
+	}
+
+	#[test]
+	fn test_include_parse_error() {
+		let resolver: MemoryResolver = [
+			("a", "${@include b}"),
+			("b", "Hello ${name"),
+		].into_iter().collect();
+		let_assert!(Err(ParseError::Include(e)) = Template::from_str_with_includes("${@include a}", &resolver, None));
+		let_assert!(IncludeErrorKind::Parse(inner) = &e.kind);
+		let_assert!(ParseError::MissingClosingBrace(inner) = &**inner);
+		assert!(inner.position == 7);
+		assert!(e.chain == ["a", "b"]);
+		assert!(e.to_string() == "Missing closing brace (in included template \"b\", included via \"a\")");
+	}
+
+	#[test]
+	fn test_include_expand_error() {
+		let resolver: MemoryResolver = [("a", "Hello ${name}!")].into_iter().collect();
+		let source = "${@include a}";
+		let_assert!(Ok(template) = Template::from_str_with_includes(source, &resolver, None));
+		let_assert!(Err(e) = template.expand(&BTreeMap::<String, String>::new()));
+		let e = Error::from(e);
+		assert!(e.to_string() == "No such variable: $name (in included template \"a\")");
+		assert!(e.source_range() == (0..13));
+	}
+
+	#[test]
+	fn test_include_root_cycle() {
+		let resolver: MemoryResolver = [
+			("a", "${@include b}"),
+			("b", "${@include a}"),
+		].into_iter().collect();
+		let_assert!(Err(ParseError::Include(e)) = Template::from_str_with_includes("${@include b}", &resolver, Some("a")));
+		assert!(e.kind == IncludeErrorKind::Cycle);
+		assert!(e.chain == ["b", "a"]);
+		assert!(e.to_string() == "Include cycle: \"b\" -> \"a\"");
+
+		let_assert!(Err(ParseError::Include(e)) = Template::from_str_with_includes("${@include a}", &resolver, Some("a")));
+		assert!(e.chain == ["a"]);
+	}
+
+	#[test]
+	fn test_file_resolver() {
+		let dir = TempDir::new("include");
+		dir.write("common/tls.conf", "tls = ${@include ../name.conf}");
+		dir.write("name.conf", "$name");
+
+		let resolver = FileResolver::new(dir.path());
+		let mut variables = BTreeMap::new();
+		variables.insert("name", "on");
+		let_assert!(Ok(template) = Template::from_str_with_includes("${@include common/tls.conf}", &resolver, None));
+		let_assert!(Ok(expanded) = template.expand(&variables));
+		assert!(expanded == "tls = on");
+	}
+
+	#[test]
+	fn test_file_resolver_root_cycle() {
+		let dir = TempDir::new("include-root-cycle");
+		let root = dir.write("a.conf", "${@include b.conf}");
+		let included = dir.write("b.conf", "${@include a.conf}");
+		let root = std::fs::canonicalize(root).unwrap().to_string_lossy().into_owned();
+		let included = std::fs::canonicalize(included).unwrap().to_string_lossy().into_owned();
+
+		// The root file is not loaded again: the include cycle is reported at the directive in the included file.
+		let resolver = FileResolver::new(dir.path());
+		let_assert!(Err(ParseError::Include(e)) = Template::from_str_with_includes("${@include b.conf}", &resolver, Some(&root)));
+		assert!(e.kind == IncludeErrorKind::Cycle);
+		assert!(e.chain == [included, root]);
+	}
+}
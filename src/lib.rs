@@ -9,7 +9,17 @@
 //! * Long format: `"Hello ${name}!"`
 //! * Default values: `"Hello ${name:person}!"`
 //! * Recursive substitution in default values: `"${XDG_CONFIG_HOME:$HOME/.config}/my-app/config.toml"`
+//! * Assign default values to variables for later use: `"${PORT:=8080}"`.
+//! * Include other templates while parsing: `"${@include logging.conf}"`.
+//! * Look up scheme-prefixed variables in different maps: `"${@env:HOME}"` or `"${@file:token}"`.
+//!   * Read variables from files in a directory, like Docker secrets: route `file` to `FileDir::new("/run/secrets")`.
+//!     The key must be a plain file name, without path separators or a leading dot.
+//! * Load variables from `.env` files, with substitution in the values.
+//! * Restrict the variables that a template can use with allow and deny lists.
 //! * Perform substitution on all string values in TOML, JSON or YAML data (optional, requires the `toml`, `json` or `yaml` feature).
+//! * Use TOML, JSON or YAML data as variables, with dotted paths for nested values (optional, requires the `toml`, `json` or `yaml` feature).
+//! * Parse templates while deserializing with `serde` (optional, requires the `serde` feature).
+//! * Report errors as `miette` or `codespan-reporting` diagnostics (optional, requires the `miette` or `codespan-reporting` feature).
 //!
 //! Variable names can consist of alphanumeric characters and underscores.
 //! They are allowed to start with numbers.
@@ -83,11 +93,16 @@
 #![warn(missing_docs, missing_debug_implementations)]
 #![cfg_attr(feature = "doc-cfg", feature(doc_cfg))]
 
+pub mod dotenv;
+
 pub mod error;
 pub use error::Error;
 
+pub mod include;
+pub use include::IncludeResolver;
+
 pub mod map;
-pub use map::{Env, EnvBytes, NoSubstitution, VariableMap};
+pub use map::{Env, EnvBytes, IterableVariableMap, NoSubstitution, VariableMap, VariableMapMut};
 
 mod template;
 pub use template::*;
@@ -98,13 +113,16 @@ pub use features::*;
 
 mod non_aliasing;
 
+#[cfg(test)]
+mod test_util;
+
 /// Substitute variables in a string.
 ///
 /// Variables have the form `$NAME`, `${NAME}` or `${NAME:default}`.
 /// A variable name can only consist of ASCII letters, digits and underscores.
 /// They are allowed to start with numbers.
 ///
-/// You can escape dollar signs, backslashes, colons and braces with a backslash.
+/// You can escape dollar signs, backslashes, colons, equals signs and braces with a backslash.
 ///
 /// You can pass either a [`HashMap`][std::collections::HashMap], [`BTreeMap`][std::collections::BTreeMap] or [`Env`] as the `variables` parameter.
 /// The maps must have [`&str`] or [`String`] keys, and the values must be [`AsRef<str>`].
@@ -123,7 +141,7 @@ where
 /// A variable name can only consist of ASCII letters, digits and underscores.
 /// They are allowed to start with numbers.
 ///
-/// You can escape dollar signs, backslashes, colons and braces with a backslash.
+/// You can escape dollar signs, backslashes, colons, equals signs and braces with a backslash.
 ///
 /// You can pass either a [`HashMap`][std::collections::HashMap], [`BTreeMap`][std::collections::BTreeMap] as the `variables` parameter.
 /// The maps must have [`&str`] or [`String`] keys, and the values must be [`AsRef<[u8]>`].
@@ -141,6 +159,7 @@ where
 #[rustfmt::skip]
 mod test {
 	use super::*;
+	use crate::error::ExpectedToken;
 	use assert2::{assert, check, let_assert};
 	use std::collections::BTreeMap;
 
@@ -288,7 +307,10 @@ mod test {
 
 		let source = "Hello ${name)!";
 		let_assert!(Err(e) = substitute(source, &map));
-		assert!(e.to_string() == "Unexpected character: ')', expected a closing brace ('}') or colon (':')");
+		assert!(e.to_string() == "Unexpected character: ')', expected a closing brace ('}'), a colon (':') or a variable name character");
+		let_assert!(Error::UnexpectedCharacter(inner) = &e);
+		assert!(inner.expected.tokens() == [ExpectedToken::ClosingBrace, ExpectedToken::Colon, ExpectedToken::NameCharacter]);
+		assert!(inner.expected.tokens()[0].character() == Some('}'));
 		#[rustfmt::skip]
 		assert!(e.source_highlighting(source) == concat!(
 				"  Hello ${name)!\n",
@@ -297,7 +319,7 @@ mod test {
 
 		let source = "Hello ${name❤";
 		let_assert!(Err(e) = substitute(source, &map));
-		assert!(e.to_string() == "Unexpected character: '❤', expected a closing brace ('}') or colon (':')");
+		assert!(e.to_string() == "Unexpected character: '❤', expected a closing brace ('}'), a colon (':') or a variable name character");
 		#[rustfmt::skip]
 		assert!(e.source_highlighting(source) == concat!(
 				"  Hello ${name❤\n",
@@ -306,7 +328,7 @@ mod test {
 
 		let source = b"\xE2\x98Hello ${name\xE2\x98";
 		let_assert!(Err(e) = substitute_bytes(source, &map));
-		assert!(e.to_string() == "Unexpected character: '\\xE2', expected a closing brace ('}') or colon (':')");
+		assert!(e.to_string() == "Unexpected character: '\\xE2', expected a closing brace ('}'), a colon (':') or a variable name character");
 	}
 
 	#[test]
@@ -355,6 +377,166 @@ mod test {
 		));
 	}
 
+	#[test]
+	fn test_scheme_prefixed_variables() {
+		let map: BTreeMap<&str, &str> = [("env:HOME", "/home/user"), ("file:token", "hunter2"), ("name", "world")].into_iter().collect();
+		check!(let Ok("/home/user/.config") = substitute("${@env:HOME}/.config", &map).as_deref());
+		check!(let Ok("hunter2") = substitute("${@file:token}", &map).as_deref());
+		check!(let Ok("/tmp") = substitute("${@env:TMPDIR:/tmp}", &map).as_deref());
+		check!(let Ok("world") = substitute("${@env:USER:${@env:LOGNAME:$name}}", &map).as_deref());
+		check!(let Ok(b"hunter2") = substitute_bytes(b"${@file:token}", &map).as_deref());
+
+		let source = "Hello ${@env:USER}!";
+		let_assert!(Err(e) = substitute(source, &map));
+		assert!(e.to_string() == "No such variable: ${@env:USER}");
+		#[rustfmt::skip]
+		assert!(e.source_highlighting(source) == concat!(
+				"  Hello ${@env:USER}!\n",
+				"           ^^^^^^^^\n",
+		));
+
+		let_assert!(Err(e) = substitute("${@env:USER:${@env:LOGNAME}}", &map));
+		assert!(e.to_string() == "None of ${@env:USER}, ${@env:LOGNAME} are set");
+
+		let_assert!(Err(e) = substitute("${@env}", &map));
+		assert!(e.to_string() == "Unexpected character: '}', expected a colon (':') or a variable name character");
+		let_assert!(Err(e) = substitute("${@env:}", &map));
+		assert!(e.to_string() == "Missing variable name");
+		let_assert!(Err(e) = substitute("${@:HOME}", &map));
+		assert!(e.to_string() == "Missing variable name");
+		let_assert!(Err(e) = substitute("${@env:HOME USER}", &map));
+		assert!(e.to_string() == "Unexpected character: ' ', expected a closing brace ('}') or a colon (':')");
+		let_assert!(Err(e) = substitute("${@env:HOME", &map));
+		assert!(e.to_string() == "Missing closing brace");
+	}
+
+	#[test]
+	fn test_scheme_key_escape_sequences() {
+		let map: BTreeMap<&str, &str> = [("url:http://localhost", "up"), ("file:C:\\temp", "tmp"), ("file:{a}", "braces")].into_iter().collect();
+		check!(let Ok("up") = substitute(r"${@url:http\://localhost}", &map).as_deref());
+		check!(let Ok("up") = substitute(r"${@url:http\://localhost:down}", &map).as_deref());
+		check!(let Ok("tmp") = substitute(r"${@file:C\:\\temp}", &map).as_deref());
+		check!(let Ok(b"up") = substitute_bytes(br"${@url:http\://localhost}", &map).as_deref());
+		check!(let Ok("down") = substitute(r"${@url:http\://example.com:down}", &map).as_deref());
+		check!(let Ok("braces") = substitute(r"${@file:\{a\}}", &map).as_deref());
+
+		let map: map::Router = map::Router::new().route("url", BTreeMap::from([("http://localhost".to_owned(), "up".to_owned())]));
+		check!(let Ok("up") = substitute(r"${@url:http\://localhost}", &map).as_deref());
+
+		let_assert!(Err(e) = substitute(r"${@url:http\://example.com}", &map));
+		assert!(e.to_string() == "No such variable: ${@url:http://example.com}");
+		let_assert!(Err(e) = substitute(r"${@url:http\q}", &map));
+		assert!(e.to_string() == "Invalid escape sequence: \\q");
+	}
+
+	#[test]
+	fn test_variable_not_permitted() {
+		let map: BTreeMap<&str, &str> = [("HOME", "/home/user"), ("SECRET", "hunter2")].into_iter().collect();
+		let map = map::fallback(map::deny_list(map, ["SECRET"]), BTreeMap::from([("SECRET", "none")]));
+		check!(let Ok("/home/user") = substitute("$HOME", &map).as_deref());
+
+		let source = "${HOME:${SECRET:none}}";
+		check!(let Ok("/home/user") = substitute(source, &map).as_deref());
+
+		let source = "${USER:${SECRET:none}}";
+		let_assert!(Err(e) = substitute(source, &map));
+		assert!(e.to_string() == "Variable not permitted: $SECRET");
+		#[rustfmt::skip]
+		assert!(e.source_highlighting(source) == concat!(
+				"  ${USER:${SECRET:none}}\n",
+				"           ^^^^^^\n",
+		));
+	}
+
+	#[test]
+	fn test_assign_default_values() {
+		let mut variables: BTreeMap<String, String> = BTreeMap::new();
+		variables.insert("HOST".into(), "localhost".into());
+
+		// Plain expansion treats `:=` like `:`.
+		let_assert!(Ok(template) = Template::from_str("${HOST:=example.com}:${PORT:=8080}"));
+		check!(let Ok("localhost:8080") = template.expand(&variables).as_deref());
+		check!(!variables.contains_key("PORT"));
+
+		check!(let Ok("localhost:8080") = template.expand_mut(&mut variables).as_deref());
+		check!(variables["HOST"] == "localhost");
+		check!(variables["PORT"] == "8080");
+
+		// Assigned values are used by later occurrences and later templates.
+		let_assert!(Ok(template) = Template::from_str("${URL:=http://$HOST:${PORT}/${PATH:=index.html}} $URL $PATH"));
+		check!(let Ok("http://localhost:8080/index.html http://localhost:8080/index.html index.html") = template.expand_mut(&mut variables).as_deref());
+		check!(variables["URL"] == "http://localhost:8080/index.html");
+		check!(variables["PATH"] == "index.html");
+
+		// Plain defaults are not assigned.
+		let_assert!(Ok(template) = TemplateBuf::from_string("${USER:nobody} ${USER:=root}".into()));
+		check!(let Ok("nobody root") = template.expand_mut(&mut variables).as_deref());
+		check!(variables["USER"] == "root");
+
+		// Scheme-prefixed variables are stored under their full name.
+		let_assert!(Ok(template) = Template::from_str("${@env:HOME:=/root} ${@env:HOME}"));
+		check!(let Ok("/root /root") = template.expand_mut(&mut variables).as_deref());
+		check!(variables["env:HOME"] == "/root");
+		check!(!variables.contains_key("HOME"));
+
+		let_assert!(Ok(template) = Template::from_str("${A:=$B}"));
+		let_assert!(Err(error::ExpandError::NoSuchVariable(e)) = template.expand_mut(&mut variables));
+		check!(e.name == "B");
+		check!(!variables.contains_key("A"));
+	}
+
+	#[test]
+	fn test_assign_default_values_bytes() {
+		let mut variables: BTreeMap<String, Vec<u8>> = BTreeMap::new();
+		variables.insert("HOST".into(), b"localhost".to_vec());
+
+		// Plain expansion treats `:=` like `:`.
+		let_assert!(Ok(template) = ByteTemplate::from_slice(b"${HOST:=example.com}:${PORT:=8080}"));
+		check!(let Ok(b"localhost:8080") = template.expand(&variables).as_deref());
+		check!(!variables.contains_key("PORT"));
+
+		check!(let Ok(b"localhost:8080") = template.expand_mut(&mut variables).as_deref());
+		check!(variables["HOST"] == b"localhost");
+		check!(variables["PORT"] == b"8080");
+
+		// Assigned values are used by later occurrences and later templates, also if they are not valid UTF-8.
+		let_assert!(Ok(template) = ByteTemplateBuf::from_vec(b"${URL:=http://$HOST:${PORT}/${PATH:=\xFF}} $URL".to_vec()));
+		check!(let Ok(b"http://localhost:8080/\xFF http://localhost:8080/\xFF") = template.expand_mut(&mut variables).as_deref());
+		check!(variables["URL"] == b"http://localhost:8080/\xFF");
+		check!(variables["PATH"] == b"\xFF");
+
+		// Plain defaults are not assigned.
+		let_assert!(Ok(template) = ByteTemplate::from_slice(b"${USER:nobody} ${USER:=root}"));
+		check!(let Ok(b"nobody root") = template.expand_mut(&mut variables).as_deref());
+		check!(variables["USER"] == b"root");
+
+		let_assert!(Ok(template) = ByteTemplate::from_slice(b"${A:=$B}"));
+		let_assert!(Err(error::ExpandError::NoSuchVariable(e)) = template.expand_mut(&mut variables));
+		check!(e.name == "B");
+		check!(!variables.contains_key("A"));
+	}
+
+	#[test]
+	fn test_default_value_starting_with_equals_sign() {
+		let variables: BTreeMap<String, String> = BTreeMap::new();
+
+		// An unescaped equals sign after the colon is part of the `:=` syntax, also when not assigning.
+		check!(let Ok("foo") = substitute("${X:=foo}", &variables).as_deref());
+		check!(let Ok(b"foo") = substitute_bytes(b"${X:=foo}", &variables).as_deref());
+
+		// An escaped equals sign is a literal equals sign in the default value.
+		check!(let Ok("=foo") = substitute(r"${X:\=foo}", &variables).as_deref());
+		check!(let Ok(b"=foo") = substitute_bytes(br"${X:\=foo}", &variables).as_deref());
+		check!(let Ok("a=b") = substitute("${X:a=b}", &variables).as_deref());
+		check!(let Ok("=") = substitute(r"\=", &variables).as_deref());
+
+		let mut variables = variables;
+		let_assert!(Ok(template) = Template::from_str(r"${X:\=foo} ${Y:=\=bar}"));
+		check!(let Ok("=foo =bar") = template.expand_mut(&mut variables).as_deref());
+		check!(!variables.contains_key("X"));
+		check!(variables["Y"] == "=bar");
+	}
+
 	#[test]
 	fn test_dyn_variable_map() {
 		let mut variables = BTreeMap::new();
@@ -0,0 +1,308 @@
+use std::borrow::Cow;
+
+use super::{IterableVariableMap, VariableMap};
+use crate::error::LookupError;
+
+/// A pattern to match variable names in an [`allow_list()`] or [`deny_list()`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum KeyPattern {
+	/// Match a variable name exactly.
+	Exact(String),
+
+	/// Match all variable names that start with a prefix.
+	Prefix(String),
+
+	/// Match variable names with a glob pattern.
+	///
+	/// A `*` matches any number of characters, and a `?` matches exactly one character.
+	/// All other characters match themselves.
+	Glob(String),
+}
+
+impl KeyPattern {
+	/// Create a pattern that matches a variable name exactly.
+	#[inline]
+	pub fn exact(name: impl Into<String>) -> Self {
+		Self::Exact(name.into())
+	}
+
+	/// Create a pattern that matches all variable names that start with a prefix.
+	#[inline]
+	pub fn prefix(prefix: impl Into<String>) -> Self {
+		Self::Prefix(prefix.into())
+	}
+
+	/// Create a pattern that matches variable names with a glob pattern.
+	#[inline]
+	pub fn glob(pattern: impl Into<String>) -> Self {
+		Self::Glob(pattern.into())
+	}
+
+	/// Check if the pattern matches a variable name.
+	pub fn matches(&self, name: &str) -> bool {
+		match self {
+			Self::Exact(exact) => name == exact,
+			Self::Prefix(prefix) => name.starts_with(prefix.as_str()),
+			Self::Glob(pattern) => glob_matches(pattern.as_bytes(), name.as_bytes()),
+		}
+	}
+}
+
+/// Parse a pattern from a string.
+///
+/// A string without `*` or `?` is an exact name.
+/// A string that ends with a `*` and has no other wildcards is a prefix.
+/// Any other string is a glob pattern.
+impl From<&str> for KeyPattern {
+	fn from(pattern: &str) -> Self {
+		match pattern.strip_suffix('*') {
+			_ if !pattern.contains(['*', '?']) => Self::exact(pattern),
+			Some(prefix) if !prefix.contains(['*', '?']) => Self::prefix(prefix),
+			_ => Self::glob(pattern),
+		}
+	}
+}
+
+impl From<String> for KeyPattern {
+	#[inline]
+	fn from(pattern: String) -> Self {
+		Self::from(pattern.as_str())
+	}
+}
+
+/// [`VariableMap`] produced by [`allow_list()`] and [`deny_list()`].
+///
+/// Looking up a variable that is not permitted with [`VariableMap::lookup()`] gives a [`LookupError::NotPermitted`] error.
+/// When expanding a template, this results in a [`VariableNotPermitted`][crate::error::VariableNotPermitted] error,
+/// so that attempts to access a forbidden variable can be told apart from missing variables.
+/// The plain [`VariableMap::get()`] function simply returns `None` for variables that are not permitted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessList<M> {
+	map: M,
+	rules: AccessRules,
+}
+
+/// The rules of an [`AccessList`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct AccessRules {
+	patterns: Vec<KeyPattern>,
+	allow: bool,
+}
+
+impl AccessRules {
+	/// Check if the rules permit access to a variable.
+	fn permits(&self, name: &str) -> bool {
+		self.patterns.iter().any(|pattern| pattern.matches(name)) == self.allow
+	}
+}
+
+impl<M> AccessList<M> {
+	/// Check if the map permits access to a variable.
+	#[inline]
+	pub fn permits(&self, name: &str) -> bool {
+		self.rules.permits(name)
+	}
+
+	/// Get the patterns of the list.
+	#[inline]
+	pub fn patterns(&self) -> &[KeyPattern] {
+		&self.rules.patterns
+	}
+
+	/// Get a reference to the wrapped map.
+	#[inline]
+	pub fn inner(&self) -> &M {
+		&self.map
+	}
+}
+
+impl<'a, M> VariableMap<'a> for AccessList<M>
+where
+	M: VariableMap<'a>,
+{
+	type Value = M::Value;
+
+	#[inline]
+	fn get(&'a self, key: &str) -> Option<Self::Value> {
+		self.lookup(key).ok().flatten()
+	}
+
+	fn lookup(&'a self, key: &str) -> Result<Option<Self::Value>, LookupError> {
+		if !self.permits(key) {
+			return Err(LookupError::NotPermitted);
+		}
+		self.map.lookup(key)
+	}
+}
+
+/// List the keys of the wrapped map that are permitted.
+impl<'a, M> IterableVariableMap<'a> for AccessList<M>
+where
+	M: IterableVariableMap<'a>,
+{
+	type Keys = PermittedKeys<'a, M::Keys>;
+
+	#[inline]
+	fn keys(&'a self) -> Self::Keys {
+		PermittedKeys {
+			keys: self.map.keys(),
+			rules: &self.rules,
+		}
+	}
+}
+
+/// Iterator over the permitted keys of an [`AccessList`].
+#[derive(Debug, Clone)]
+pub struct PermittedKeys<'a, I> {
+	keys: I,
+	rules: &'a AccessRules,
+}
+
+impl<'a, I> Iterator for PermittedKeys<'a, I>
+where
+	I: Iterator<Item = Cow<'a, str>>,
+{
+	type Item = Cow<'a, str>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		self.keys.by_ref().find(|key| self.rules.permits(key))
+	}
+}
+
+/// Creates a [`VariableMap`] that only permits access to variables that match one of the patterns.
+///
+/// The patterns can be [`KeyPattern`]s, or strings that are parsed as pattern:
+/// a plain name matches exactly, a name ending in `*` matches a prefix, and other names with `*` or `?` are glob patterns.
+///
+/// Scheme-prefixed variables are matched with their full name, like `env:HOME`.
+///
+/// # Example
+/// ```rust
+/// # use subst::map::allow_list;
+/// # std::env::set_var("HOME", "/home/user");
+/// # std::env::set_var("AWS_SECRET_ACCESS_KEY", "hunter2");
+/// let variables = allow_list(subst::Env, ["HOME", "APP_*", "LC_?????"]);
+///
+/// assert_eq!(subst::substitute("$HOME", &variables).unwrap(), "/home/user");
+///
+/// let error = subst::substitute("${AWS_SECRET_ACCESS_KEY:none}", &variables).unwrap_err();
+/// assert_eq!(error.to_string(), "Variable not permitted: $AWS_SECRET_ACCESS_KEY");
+/// ```
+pub fn allow_list<M, I>(map: M, patterns: I) -> AccessList<M>
+where
+	I: IntoIterator,
+	I::Item: Into<KeyPattern>,
+{
+	AccessList {
+		map,
+		rules: AccessRules {
+			patterns: patterns.into_iter().map(Into::into).collect(),
+			allow: true,
+		},
+	}
+}
+
+/// Creates a [`VariableMap`] that refuses access to variables that match one of the patterns.
+///
+/// The patterns are interpreted the same as for [`allow_list()`].
+///
+/// # Example
+/// ```rust
+/// # use subst::map::{deny_list, KeyPattern};
+/// # std::env::set_var("HOME", "/home/user");
+/// let variables = deny_list(subst::Env, [KeyPattern::prefix("AWS_"), KeyPattern::glob("*_TOKEN")]);
+///
+/// assert_eq!(subst::substitute("$HOME", &variables).unwrap(), "/home/user");
+///
+/// let error = subst::substitute("$GITHUB_TOKEN", &variables).unwrap_err();
+/// assert_eq!(error.to_string(), "Variable not permitted: $GITHUB_TOKEN");
+/// ```
+pub fn deny_list<M, I>(map: M, patterns: I) -> AccessList<M>
+where
+	I: IntoIterator,
+	I::Item: Into<KeyPattern>,
+{
+	AccessList {
+		map,
+		rules: AccessRules {
+			patterns: patterns.into_iter().map(Into::into).collect(),
+			allow: false,
+		},
+	}
+}
+
+/// Check if a glob pattern with `*` and `?` wildcards matches a name.
+fn glob_matches(pattern: &[u8], name: &[u8]) -> bool {
+	// Position in the pattern after the last `*`, and the position in the name where the `*` match ends.
+	let mut star: Option<(usize, usize)> = None;
+	let mut p = 0;
+	let mut n = 0;
+	while n < name.len() {
+		match pattern.get(p) {
+			Some(b'*') => {
+				star = Some((p + 1, n));
+				p += 1;
+			},
+			Some(b'?') => {
+				p += 1;
+				n += utf8_char_len(name[n]);
+			},
+			Some(&c) if c == name[n] => {
+				p += 1;
+				n += 1;
+			},
+			// On a mismatch, let the last `*` match one more character, and try again.
+			_ => match &mut star {
+				Some((star_p, star_n)) => {
+					*star_n += utf8_char_len(name[*star_n]);
+					p = *star_p;
+					n = *star_n;
+				},
+				None => return false,
+			},
+		}
+	}
+	pattern[p.min(pattern.len())..].iter().all(|&c| c == b'*')
+}
+
+/// Get the length of a UTF-8 encoded character from its first byte.
+fn utf8_char_len(first_byte: u8) -> usize {
+	match first_byte {
+		0xF0.. => 4,
+		0xE0.. => 3,
+		0xC0.. => 2,
+		_ => 1,
+	}
+}
+
+#[cfg(test)]
+#[rustfmt::skip]
+mod test {
+	use super::*;
+	use assert2::check;
+
+	#[test]
+	fn test_glob_matches() {
+		check!(glob_matches(b"*", b""));
+		check!(glob_matches(b"*", b"HOME"));
+		check!(glob_matches(b"HOME", b"HOME"));
+		check!(!glob_matches(b"HOME", b"HOMES"));
+		check!(glob_matches(b"AWS_*", b"AWS_SECRET_ACCESS_KEY"));
+		check!(glob_matches(b"*_KEY", b"AWS_SECRET_ACCESS_KEY"));
+		check!(glob_matches(b"*_*_KEY", b"AWS_SECRET_ACCESS_KEY"));
+		check!(!glob_matches(b"*_TOKEN", b"AWS_SECRET_ACCESS_KEY"));
+		check!(glob_matches(b"LC_?????", b"LC_CTYPE"));
+		check!(!glob_matches(b"LC_?????", b"LC_ALL"));
+		check!(glob_matches("*?".as_bytes(), "é".as_bytes()));
+		check!(glob_matches("a*?b".as_bytes(), "aééb".as_bytes()));
+		check!(!glob_matches("??".as_bytes(), "é".as_bytes()));
+	}
+
+	#[test]
+	fn test_parse_key_pattern() {
+		check!(KeyPattern::from("HOME") == KeyPattern::exact("HOME"));
+		check!(KeyPattern::from("APP_*") == KeyPattern::prefix("APP_"));
+		check!(KeyPattern::from("*_TOKEN") == KeyPattern::glob("*_TOKEN"));
+		check!(KeyPattern::from("LC_?*") == KeyPattern::glob("LC_?*"));
+	}
+}
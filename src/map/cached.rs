@@ -0,0 +1,229 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use super::{IterableVariableMap, VariableMap};
+use crate::error::LookupError;
+
+/// A map that remembers the results of lookups in another map.
+///
+/// Each key is looked up in the wrapped map at most once.
+/// Missing variables are remembered too, so a key that was not found is not looked up again either.
+/// Refused lookups are not cached (see [`VariableMap::lookup()`]).
+/// This is useful for maps with slow lookups, like [`from_fn()`][super::from_fn] with a closure that reads files.
+///
+/// The cache stores the values, so the values of the wrapped map must implement [`Clone`],
+/// and they can not borrow from the wrapped map itself.
+/// A map that gives references to its own values, like a `HashMap<String, String>`, can not be cached directly.
+/// Wrap it with [`map_value()`][super::map_value] to clone the values first:
+///
+/// ```
+/// # use std::collections::HashMap;
+/// use subst::map::{map_value, Cached, VariableMap};
+///
+/// let variables: HashMap<String, String> = [("HOST".into(), "localhost".into())].into_iter().collect();
+/// let variables = Cached::new(map_value(variables, String::clone));
+/// assert_eq!(variables.get("HOST").as_deref(), Some("localhost"));
+/// ```
+///
+/// Cloning the map is cheap: all clones share the wrapped map and the cache through an [`Arc`].
+/// The cache is protected by a mutex, so clones can be used from different threads.
+///
+/// # Example
+/// ```
+/// # use std::sync::atomic::{AtomicUsize, Ordering};
+/// use subst::map::{from_fn, Cached};
+///
+/// let calls = AtomicUsize::new(0);
+/// let variables = Cached::new(from_fn(|name: &str| {
+///   calls.fetch_add(1, Ordering::Relaxed);
+///   (name == "HOST").then(|| String::from("localhost"))
+/// }));
+///
+/// let expanded = subst::substitute("$HOST ${PORT:80} $HOST ${PORT:8080}", &variables).unwrap();
+/// assert_eq!(expanded, "localhost 80 localhost 8080");
+/// assert_eq!(calls.load(Ordering::Relaxed), 2);
+/// ```
+pub struct Cached<M, V> {
+	inner: Arc<CachedInner<M, V>>,
+}
+
+/// The shared state of a [`Cached`] map.
+struct CachedInner<M, V> {
+	map: M,
+	cache: Mutex<HashMap<String, Option<V>>>,
+}
+
+impl<M, V> Cached<M, V> {
+	/// Create a new map that remembers the results of lookups in the given map.
+	#[inline]
+	pub fn new<'a>(map: M) -> Self
+	where
+		M: VariableMap<'a, Value = V>,
+	{
+		Self {
+			inner: Arc::new(CachedInner {
+				map,
+				cache: Mutex::new(HashMap::new()),
+			}),
+		}
+	}
+
+	/// Get a reference to the wrapped map.
+	#[inline]
+	pub fn inner(&self) -> &M {
+		&self.inner.map
+	}
+
+	/// Get the number of keys in the cache, including keys that were not found.
+	pub fn len(&self) -> usize {
+		self.inner.cache.lock().unwrap().len()
+	}
+
+	/// Check if the cache is empty.
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	/// Forget all cached results, so that all keys are looked up again.
+	///
+	/// This also affects all clones of the map.
+	pub fn clear(&self) {
+		self.inner.cache.lock().unwrap().clear();
+	}
+}
+
+impl<M, V> Clone for Cached<M, V> {
+	#[inline]
+	fn clone(&self) -> Self {
+		Self {
+			inner: self.inner.clone(),
+		}
+	}
+}
+
+impl<M: std::fmt::Debug, V> std::fmt::Debug for Cached<M, V> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("Cached")
+			.field("map", &self.inner.map)
+			.field("cached_keys", &self.len())
+			.finish()
+	}
+}
+
+impl<'a, M, V> VariableMap<'a> for Cached<M, V>
+where
+	M: VariableMap<'a, Value = V>,
+	V: Clone,
+{
+	type Value = V;
+
+	#[inline]
+	fn get(&'a self, key: &str) -> Option<Self::Value> {
+		self.lookup(key).ok().flatten()
+	}
+
+	fn lookup(&'a self, key: &str) -> Result<Option<Self::Value>, LookupError> {
+		if let Some(value) = self.inner.cache.lock().unwrap().get(key) {
+			return Ok(value.clone());
+		}
+		// Do not hold the lock during the lookup, so slow lookups do not block other threads.
+		let value = self.inner.map.lookup(key)?;
+		self.inner.cache.lock().unwrap().insert(key.to_owned(), value.clone());
+		Ok(value)
+	}
+}
+
+/// List the keys of the wrapped map.
+///
+/// The keys are not cached.
+impl<'a, M, V> IterableVariableMap<'a> for Cached<M, V>
+where
+	M: IterableVariableMap<'a, Value = V>,
+	V: Clone,
+{
+	type Keys = M::Keys;
+
+	#[inline]
+	fn keys(&'a self) -> Self::Keys {
+		self.inner.map.keys()
+	}
+}
+
+#[cfg(test)]
+#[rustfmt::skip]
+mod test {
+	use std::collections::BTreeMap;
+	use std::sync::atomic::{AtomicUsize, Ordering};
+
+	use super::*;
+	use crate::map::{deny_list, from_fn, Recursive};
+	use assert2::{check, let_assert};
+
+	#[test]
+	fn test_missing_keys_are_cached() {
+		let calls = AtomicUsize::new(0);
+		let variables = Cached::new(from_fn(|_name: &str| {
+			calls.fetch_add(1, Ordering::Relaxed);
+			None::<String>
+		}));
+		check!(variables.get("PORT") == None);
+		check!(variables.get("PORT") == None);
+		check!(variables.lookup("PORT") == Ok(None));
+		check!(calls.load(Ordering::Relaxed) == 1);
+		check!(variables.len() == 1);
+	}
+
+	#[test]
+	fn test_clones_share_cache() {
+		let calls = AtomicUsize::new(0);
+		let variables = Cached::new(from_fn(|name: &str| {
+			calls.fetch_add(1, Ordering::Relaxed);
+			Some(name.to_lowercase())
+		}));
+		let clone = variables.clone();
+		check!(variables.get("HOST").as_deref() == Some("host"));
+		check!(clone.get("HOST").as_deref() == Some("host"));
+		check!(calls.load(Ordering::Relaxed) == 1);
+		check!(clone.len() == 1);
+
+		clone.clear();
+		check!(variables.is_empty());
+	}
+
+	#[test]
+	fn test_clear() {
+		let calls = AtomicUsize::new(0);
+		let variables = Cached::new(from_fn(|name: &str| {
+			calls.fetch_add(1, Ordering::Relaxed);
+			Some(name.to_lowercase())
+		}));
+		check!(variables.get("HOST").as_deref() == Some("host"));
+		variables.clear();
+		check!(variables.is_empty());
+		check!(variables.get("HOST").as_deref() == Some("host"));
+		check!(calls.load(Ordering::Relaxed) == 2);
+	}
+
+	#[test]
+	fn test_errors_are_not_cached() {
+		let calls = AtomicUsize::new(0);
+		let variables = Cached::new(deny_list(from_fn(|name: &str| {
+			calls.fetch_add(1, Ordering::Relaxed);
+			Some(name.to_lowercase())
+		}), ["SECRET"]));
+		let_assert!(Err(LookupError::NotPermitted) = variables.lookup("SECRET"));
+		let_assert!(Err(LookupError::NotPermitted) = variables.lookup("SECRET"));
+		check!(variables.get("SECRET") == None);
+		check!(variables.is_empty());
+		check!(calls.load(Ordering::Relaxed) == 0);
+
+		let variables = Cached::new(Recursive::new(BTreeMap::from([("A", "$B"), ("B", "$A")])));
+		let_assert!(Err(LookupError::Cycle(cycle)) = variables.lookup("A"));
+		check!(cycle == ["A", "B", "A"]);
+		let_assert!(Err(LookupError::Cycle(_)) = variables.lookup("A"));
+		check!(variables.is_empty());
+
+		let_assert!(Err(crate::Error::Cycle(e)) = crate::substitute("${A:default}", &variables));
+		check!(e.cycle == ["A", "B", "A"]);
+	}
+}
@@ -0,0 +1,260 @@
+use std::borrow::Cow;
+use std::ffi::OsString;
+
+use super::{IterableVariableMap, VariableMap};
+
+/// A map that gives strings from environment variables with a common prefix.
+///
+/// A variable name from the template is transformed and prefixed to get the name of the environment variable.
+/// By default, the name is used as-is.
+/// Use [`Self::uppercase()`] and [`Self::replace_separators()`] to write templates in a different style than the environment.
+///
+/// The map can also list the variables it gives access to, with the names to use in a template.
+/// Use [`Self::bytes()`] to get a map that gives byte strings instead.
+///
+/// # Example
+/// ```
+/// # use subst::map::{EnvPrefixed, VariableMap};
+/// std::env::set_var("MYAPP_DB_HOST", "db.example.com");
+///
+/// let variables = EnvPrefixed::new("MYAPP_").uppercase().replace_separators();
+/// assert_eq!(variables.get("db_host").as_deref(), Some("db.example.com"));
+/// assert_eq!(variables.get("db.host").as_deref(), Some("db.example.com"));
+/// assert_eq!(variables.get("DB_HOST").as_deref(), Some("db.example.com"));
+/// assert_eq!(subst::substitute("${db_host}", &variables).unwrap(), "db.example.com");
+///
+/// // List all variables with the prefix.
+/// assert!(variables.vars().contains(&("db_host".into(), "db.example.com".into())));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnvPrefixed {
+	prefix: String,
+	uppercase: bool,
+	replace_separators: bool,
+}
+
+impl EnvPrefixed {
+	/// Create a new map that looks up environment variables with the given prefix.
+	#[inline]
+	pub fn new(prefix: impl Into<String>) -> Self {
+		Self {
+			prefix: prefix.into(),
+			uppercase: false,
+			replace_separators: false,
+		}
+	}
+
+	/// Convert variable names to uppercase before looking them up.
+	#[inline]
+	pub fn uppercase(mut self) -> Self {
+		self.uppercase = true;
+		self
+	}
+
+	/// Replace `-` and `.` in variable names with `_` before looking them up.
+	#[inline]
+	pub fn replace_separators(mut self) -> Self {
+		self.replace_separators = true;
+		self
+	}
+
+	/// Get the prefix for environment variables.
+	#[inline]
+	pub fn prefix(&self) -> &str {
+		&self.prefix
+	}
+
+	/// Get the name of the environment variable for a variable name.
+	pub fn env_name(&self, name: &str) -> String {
+		let mut env_name = String::with_capacity(self.prefix.len() + name.len());
+		env_name.push_str(&self.prefix);
+		for c in name.chars() {
+			let c = match c {
+				'-' | '.' if self.replace_separators => '_',
+				c if self.uppercase => c.to_ascii_uppercase(),
+				c => c,
+			};
+			env_name.push(c);
+		}
+		env_name
+	}
+
+	/// Get the variable name for the name of an environment variable.
+	///
+	/// This is the reverse of [`Self::env_name()`]: the prefix is removed,
+	/// and if names are converted to uppercase, the rest of the name is converted to lowercase.
+	///
+	/// Returns `None` if the environment variable does not start with the prefix,
+	/// or if no variable name maps to it, like `MYAPP_db_host` for a map that converts names to uppercase.
+	pub fn var_name(&self, env_name: &str) -> Option<String> {
+		let name = env_name.strip_prefix(self.prefix.as_str())?;
+		if name.is_empty() {
+			return None;
+		}
+		let name = match self.uppercase {
+			true => name.to_ascii_lowercase(),
+			false => name.to_owned(),
+		};
+		(self.env_name(&name) == env_name).then_some(name)
+	}
+
+	/// Get all environment variables that can be looked up with this map and have a valid Unicode value.
+	///
+	/// Returns the variable name of each environment variable, as given by [`Self::var_name()`], and its value.
+	pub fn vars(&self) -> Vec<(String, String)> {
+		self.env_vars()
+			.filter_map(|(name, value)| Some((name, value.into_string().ok()?)))
+			.collect()
+	}
+
+	/// Get a map that gives the values of the environment variables as byte strings.
+	///
+	/// Only available on Unix platforms.
+	///
+	/// # Example
+	/// ```
+	/// # use subst::map::{EnvPrefixed, VariableMap};
+	/// std::env::set_var("MYAPP_NAME", "app");
+	///
+	/// let variables = EnvPrefixed::new("MYAPP_").uppercase();
+	/// assert_eq!(subst::substitute_bytes(b"$name", &variables.bytes()).unwrap(), b"app");
+	/// ```
+	#[cfg(unix)]
+	#[inline]
+	pub fn bytes(&self) -> EnvPrefixedBytes<'_> {
+		EnvPrefixedBytes { inner: self }
+	}
+
+	/// Iterate over the environment variables that can be looked up with this map, with their variable names.
+	///
+	/// Environment variables with a name that is not valid Unicode are skipped.
+	fn env_vars(&self) -> impl Iterator<Item = (String, OsString)> + '_ {
+		std::env::vars_os().filter_map(|(key, value)| Some((self.var_name(key.to_str()?)?, value)))
+	}
+}
+
+impl<'a> VariableMap<'a> for EnvPrefixed {
+	type Value = String;
+
+	#[inline]
+	fn get(&'a self, key: &str) -> Option<Self::Value> {
+		std::env::var(self.env_name(key)).ok()
+	}
+}
+
+/// List the variable names of the environment variables that have a valid Unicode value.
+impl<'a> IterableVariableMap<'a> for EnvPrefixed {
+	type Keys = std::vec::IntoIter<Cow<'a, str>>;
+
+	fn keys(&'a self) -> Self::Keys {
+		let keys: Vec<_> = self.vars().into_iter().map(|(name, _value)| Cow::Owned(name)).collect();
+		keys.into_iter()
+	}
+}
+
+/// A map that gives byte strings from environment variables with a common prefix.
+///
+/// Created with [`EnvPrefixed::bytes()`].
+///
+/// Only available on Unix platforms.
+#[cfg(unix)]
+#[derive(Debug, Clone, Copy)]
+pub struct EnvPrefixedBytes<'a> {
+	inner: &'a EnvPrefixed,
+}
+
+#[cfg(unix)]
+impl EnvPrefixedBytes<'_> {
+	/// Get all environment variables that can be looked up with this map.
+	///
+	/// Returns the variable name of each environment variable, as given by [`EnvPrefixed::var_name()`], and its value.
+	pub fn vars(&self) -> Vec<(String, Vec<u8>)> {
+		use std::os::unix::ffi::OsStringExt;
+		self.inner
+			.env_vars()
+			.map(|(name, value)| (name, value.into_vec()))
+			.collect()
+	}
+}
+
+#[cfg(unix)]
+impl<'a> VariableMap<'a> for EnvPrefixedBytes<'_> {
+	type Value = Vec<u8>;
+
+	#[inline]
+	fn get(&'a self, key: &str) -> Option<Self::Value> {
+		use std::os::unix::ffi::OsStringExt;
+		let value = std::env::var_os(self.inner.env_name(key))?;
+		Some(value.into_vec())
+	}
+}
+
+/// List the variable names of the environment variables.
+#[cfg(unix)]
+impl<'a> IterableVariableMap<'a> for EnvPrefixedBytes<'_> {
+	type Keys = std::vec::IntoIter<Cow<'a, str>>;
+
+	fn keys(&'a self) -> Self::Keys {
+		let keys: Vec<_> = self.inner.env_vars().map(|(name, _value)| Cow::Owned(name)).collect();
+		keys.into_iter()
+	}
+}
+
+#[cfg(test)]
+#[rustfmt::skip]
+mod test {
+	use super::*;
+	use assert2::{check, let_assert};
+
+	#[test]
+	fn test_env_name() {
+		let variables = EnvPrefixed::new("APP_");
+		check!(variables.env_name("db.host") == "APP_db.host");
+		check!(variables.var_name("APP_db.host").as_deref() == Some("db.host"));
+
+		let variables = EnvPrefixed::new("APP_").uppercase().replace_separators();
+		check!(variables.env_name("db.host-name") == "APP_DB_HOST_NAME");
+		check!(variables.var_name("APP_DB_HOST_NAME").as_deref() == Some("db_host_name"));
+		check!(variables.var_name("APP_db_host") == None);
+		check!(variables.var_name("APP_") == None);
+		check!(variables.var_name("OTHER_DB_HOST") == None);
+	}
+
+	#[test]
+	fn test_lookup_and_list() {
+		std::env::set_var("SUBST_TEST_ENV_PREFIXED_DB_HOST", "localhost");
+		std::env::set_var("SUBST_TEST_ENV_PREFIXED_DB_PORT", "5432");
+		std::env::set_var("SUBST_TEST_ENV_PREFIXED_lower", "unreachable");
+
+		let variables = EnvPrefixed::new("SUBST_TEST_ENV_PREFIXED_").uppercase().replace_separators();
+		check!(variables.get("db.host").as_deref() == Some("localhost"));
+		check!(variables.get("lower") == None);
+
+		let mut vars = variables.vars();
+		vars.sort();
+		check!(vars == [("db_host".into(), "localhost".into()), ("db_port".into(), "5432".into())]);
+
+		let mut keys: Vec<_> = variables.keys().collect();
+		keys.sort();
+		check!(keys == ["db_host", "db_port"]);
+
+		let_assert!(Err(crate::Error::NoSuchVariable(e)) = crate::substitute("$db_hots", &variables));
+		check!(e.with_suggestions(&variables).suggestions == ["db_host"]);
+	}
+
+	#[test]
+	#[cfg(unix)]
+	fn test_bytes() {
+		use std::os::unix::ffi::OsStrExt;
+		std::env::set_var("SUBST_TEST_ENV_PREFIXED_BYTES_NAME", std::ffi::OsStr::from_bytes(b"\xFFapp"));
+
+		let variables = EnvPrefixed::new("SUBST_TEST_ENV_PREFIXED_BYTES_").uppercase();
+		check!(variables.get("name") == None);
+		check!(variables.vars().is_empty());
+
+		let bytes = variables.bytes();
+		check!(bytes.get("name").as_deref() == Some(&b"\xFFapp"[..]));
+		check!(bytes.vars() == [("name".into(), b"\xFFapp".to_vec())]);
+		check!(bytes.keys().collect::<Vec<_>>() == ["name"]);
+	}
+}
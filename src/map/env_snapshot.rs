@@ -0,0 +1,249 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::ffi::{OsStr, OsString};
+
+use super::{IterableVariableMap, VariableMap, VariableMapMut};
+
+/// A map that gives strings from a snapshot of the environment.
+///
+/// Unlike [`Env`][super::Env], the environment is read only once, when the snapshot is taken.
+/// This makes lookups fast, and guarantees that a template is expanded with a consistent set of variables,
+/// even if other threads modify the environment during expansion.
+///
+/// Variables can be added to or removed from the snapshot without affecting the real environment.
+/// This makes it easy to test code that expands templates with environment variables.
+///
+/// The snapshot gives string values.
+/// Variables that are not valid Unicode are treated as missing, just like with [`Env`][super::Env].
+/// Use [`Self::bytes()`] to get a map that gives byte strings instead.
+///
+/// # Example
+/// ```
+/// # use subst::map::{EnvSnapshot, VariableMap};
+/// let mut variables = EnvSnapshot::capture();
+/// variables.set_var("HOME", "/home/test");
+/// variables.remove_var("XDG_CONFIG_HOME");
+///
+/// let path = subst::substitute("${XDG_CONFIG_HOME:$HOME/.config}/my-app", &variables).unwrap();
+/// assert_eq!(path, "/home/test/.config/my-app");
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EnvSnapshot {
+	vars: BTreeMap<OsString, OsString>,
+}
+
+impl EnvSnapshot {
+	/// Take a snapshot of the environment of the current process.
+	pub fn capture() -> Self {
+		Self {
+			vars: std::env::vars_os().collect(),
+		}
+	}
+
+	/// Create an empty snapshot without any variables.
+	#[inline]
+	pub fn empty() -> Self {
+		Self::default()
+	}
+
+	/// Set a variable in the snapshot.
+	///
+	/// If the variable was already present, the value is replaced.
+	/// This does not affect the environment of the process.
+	///
+	/// Unlike [`VariableMapMut::set()`], this accepts names and values that are not valid Unicode.
+	#[inline]
+	pub fn set_var(&mut self, key: impl Into<OsString>, value: impl Into<OsString>) -> &mut Self {
+		self.vars.insert(key.into(), value.into());
+		self
+	}
+
+	/// Remove a variable from the snapshot.
+	///
+	/// Returns the previous value of the variable, if it was present.
+	/// This does not affect the environment of the process.
+	#[inline]
+	pub fn remove_var(&mut self, key: impl AsRef<OsStr>) -> Option<OsString> {
+		self.vars.remove(key.as_ref())
+	}
+
+	/// Get the value of a variable from the snapshot, even if it is not valid Unicode.
+	#[inline]
+	pub fn get_os(&self, key: impl AsRef<OsStr>) -> Option<&OsStr> {
+		self.vars.get(key.as_ref()).map(OsString::as_os_str)
+	}
+
+	/// Iterate over all variables in the snapshot, ordered by name.
+	#[inline]
+	pub fn iter(&self) -> impl Iterator<Item = (&OsStr, &OsStr)> {
+		self.vars
+			.iter()
+			.map(|(key, value)| (key.as_os_str(), value.as_os_str()))
+	}
+
+	/// Get a map that gives byte strings from the snapshot.
+	///
+	/// Only available on Unix platforms.
+	///
+	/// # Example
+	/// ```
+	/// # use subst::map::EnvSnapshot;
+	/// let variables: EnvSnapshot = [("NAME", "world")].into_iter().collect();
+	/// let expanded = subst::substitute_bytes(b"Hello $NAME!", &variables.bytes()).unwrap();
+	/// assert_eq!(expanded, b"Hello world!");
+	/// ```
+	#[cfg(unix)]
+	#[inline]
+	pub fn bytes(&self) -> EnvSnapshotBytes<'_> {
+		EnvSnapshotBytes { snapshot: self }
+	}
+}
+
+impl<K, V> FromIterator<(K, V)> for EnvSnapshot
+where
+	K: Into<OsString>,
+	V: Into<OsString>,
+{
+	fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+		let vars = iter.into_iter().map(|(k, v)| (k.into(), v.into())).collect();
+		Self { vars }
+	}
+}
+
+impl<'a> VariableMap<'a> for EnvSnapshot {
+	type Value = &'a str;
+
+	#[inline]
+	fn get(&'a self, key: &str) -> Option<Self::Value> {
+		self.vars.get(OsStr::new(key))?.to_str()
+	}
+}
+
+/// Set variables in the snapshot, without affecting the environment of the process.
+impl VariableMapMut for EnvSnapshot {
+	#[inline]
+	fn set(&mut self, key: &str, value: String) {
+		self.vars.insert(key.into(), value.into());
+	}
+}
+
+/// List the names of all variables in the snapshot with a valid Unicode name and value.
+impl<'a> IterableVariableMap<'a> for EnvSnapshot {
+	#[allow(clippy::type_complexity)]
+	type Keys = std::iter::FilterMap<
+		std::collections::btree_map::Iter<'a, OsString, OsString>,
+		fn((&'a OsString, &'a OsString)) -> Option<Cow<'a, str>>,
+	>;
+
+	#[inline]
+	fn keys(&'a self) -> Self::Keys {
+		self.vars.iter().filter_map(|(key, value)| {
+			value.to_str()?;
+			Some(Cow::Borrowed(key.to_str()?))
+		})
+	}
+}
+
+/// A map that gives byte strings from an [`EnvSnapshot`].
+///
+/// Only available on Unix platforms.
+#[cfg(unix)]
+#[derive(Debug, Clone, Copy)]
+pub struct EnvSnapshotBytes<'a> {
+	snapshot: &'a EnvSnapshot,
+}
+
+#[cfg(unix)]
+impl<'a> VariableMap<'a> for EnvSnapshotBytes<'_> {
+	type Value = &'a [u8];
+
+	#[inline]
+	fn get(&'a self, key: &str) -> Option<Self::Value> {
+		use std::os::unix::ffi::OsStrExt;
+		Some(self.snapshot.vars.get(OsStr::new(key))?.as_bytes())
+	}
+}
+
+/// List the names of all variables in the snapshot with a valid Unicode name.
+#[cfg(unix)]
+impl<'a> IterableVariableMap<'a> for EnvSnapshotBytes<'_> {
+	#[allow(clippy::type_complexity)]
+	type Keys = std::iter::FilterMap<
+		std::collections::btree_map::Keys<'a, OsString, OsString>,
+		fn(&'a OsString) -> Option<Cow<'a, str>>,
+	>;
+
+	#[inline]
+	fn keys(&'a self) -> Self::Keys {
+		self.snapshot
+			.vars
+			.keys()
+			.filter_map(|key| Some(Cow::Borrowed(key.to_str()?)))
+	}
+}
+
+#[cfg(test)]
+#[rustfmt::skip]
+mod test {
+	use super::*;
+	use assert2::check;
+
+	#[test]
+	fn test_set_and_remove() {
+		let mut variables = EnvSnapshot::empty();
+		variables.set_var("NAME", "world").set_var("GREETING", "Hello");
+		check!(variables.get("NAME") == Some("world"));
+		check!(crate::substitute("$GREETING $NAME", &variables).unwrap() == "Hello world");
+
+		variables.set_var("NAME", "you");
+		check!(variables.get("NAME") == Some("you"));
+
+		check!(variables.remove_var("NAME") == Some("you".into()));
+		check!(variables.remove_var("NAME") == None);
+		check!(variables.get("NAME") == None);
+		check!(variables.iter().collect::<Vec<_>>() == [(OsStr::new("GREETING"), OsStr::new("Hello"))]);
+	}
+
+	#[test]
+	fn test_variable_map_mut() {
+		let mut variables = EnvSnapshot::empty();
+		VariableMapMut::set(&mut variables, "NAME", "world".into());
+		check!(variables.get("NAME") == Some("world"));
+
+		let mut variables = EnvSnapshot::empty();
+		let template = crate::Template::from_str("${NAME:=world}").unwrap();
+		check!(template.expand_mut(&mut variables).unwrap() == "world");
+		check!(variables.get("NAME") == Some("world"));
+	}
+
+	#[test]
+	fn test_does_not_affect_environment() {
+		std::env::set_var("SUBST_TEST_ENV_SNAPSHOT", "original");
+		let mut variables = EnvSnapshot::capture();
+		check!(variables.get("SUBST_TEST_ENV_SNAPSHOT") == Some("original"));
+
+		variables.set_var("SUBST_TEST_ENV_SNAPSHOT", "changed");
+		variables.set_var("SUBST_TEST_ENV_SNAPSHOT_NEW", "new");
+		check!(std::env::var("SUBST_TEST_ENV_SNAPSHOT").as_deref() == Ok("original"));
+		check!(std::env::var_os("SUBST_TEST_ENV_SNAPSHOT_NEW") == None);
+
+		std::env::set_var("SUBST_TEST_ENV_SNAPSHOT", "later");
+		check!(variables.get("SUBST_TEST_ENV_SNAPSHOT") == Some("changed"));
+	}
+
+	#[test]
+	#[cfg(unix)]
+	fn test_non_unicode() {
+		use std::os::unix::ffi::OsStrExt;
+		let mut variables = EnvSnapshot::empty();
+		variables.set_var("NAME", OsStr::from_bytes(b"\xFFworld"));
+		variables.set_var(OsStr::from_bytes(b"\xFF"), "value");
+		check!(variables.get("NAME") == None);
+		check!(variables.get_os("NAME") == Some(OsStr::from_bytes(b"\xFFworld")));
+		check!(variables.keys().count() == 0);
+
+		let bytes = variables.bytes();
+		check!(bytes.get("NAME") == Some(&b"\xFFworld"[..]));
+		check!(bytes.keys().collect::<Vec<_>>() == ["NAME"]);
+	}
+}
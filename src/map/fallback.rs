@@ -1,4 +1,5 @@
-use super::VariableMap;
+use super::{IterableVariableMap, VariableMap};
+use crate::error::LookupError;
 
 /// [`VariableMap`] produced by [`fallback()`].
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
@@ -17,6 +18,26 @@ where
 	fn get(&'a self, key: &str) -> Option<Self::Value> {
 		self.base.get(key).or_else(|| self.fallback.get(key))
 	}
+
+	fn lookup(&'a self, key: &str) -> Result<Option<Self::Value>, LookupError> {
+		match self.base.lookup(key)? {
+			Some(value) => Ok(Some(value)),
+			None => self.fallback.lookup(key),
+		}
+	}
+}
+
+impl<'a, BaseMap, FallbackMap> IterableVariableMap<'a> for Fallback<BaseMap, FallbackMap>
+where
+	BaseMap: IterableVariableMap<'a>,
+	FallbackMap: IterableVariableMap<'a, Value = BaseMap::Value>,
+{
+	type Keys = std::iter::Chain<BaseMap::Keys, FallbackMap::Keys>;
+
+	#[inline]
+	fn keys(&'a self) -> Self::Keys {
+		self.base.keys().chain(self.fallback.keys())
+	}
 }
 
 /// Creates a [`VariableMap`] that will first try to find values in `base`, and then attempt to
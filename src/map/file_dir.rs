@@ -0,0 +1,413 @@
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use super::VariableMap;
+
+/// A map that gives the contents of files in a directory, like Docker or Kubernetes secrets.
+///
+/// The variable `${DB_PASSWORD}` is resolved by reading the file `DB_PASSWORD` in the directory.
+/// Use [`Self::map_name()`] to change how variable names are mapped to file names.
+///
+/// Optionally, the map also supports the `*_FILE` convention:
+/// if the environment variable `DB_PASSWORD_FILE` is set, `${DB_PASSWORD}` is resolved by reading the file it points to.
+/// See [`Self::file_env()`].
+///
+/// Files that do not exist or can not be read are treated as missing variables.
+/// Variable names that contain path separators or that start with a `.` are never looked up.
+/// This also applies to the key of a scheme-prefixed variable when the map is used in a [`Router`][super::Router]:
+/// with `FileDir::new("/run/secrets")` routed to `file`, use `${@file:token}` instead of `${@file:/run/secrets/token}`.
+///
+/// The map gives string values, and treats files that are not valid UTF-8 as missing.
+/// Use [`Self::bytes()`] to get a map that gives byte strings instead.
+///
+/// # Example
+/// ```
+/// # fn main() -> std::io::Result<()> {
+/// # let dir = std::env::temp_dir().join(format!("subst-file-dir-doc-{}", std::process::id()));
+/// # std::fs::create_dir_all(&dir)?;
+/// use subst::map::{FileDir, VariableMap};
+///
+/// std::fs::write(dir.join("db_password"), "hunter2\n")?;
+/// let secrets = FileDir::new(&dir)
+///   .map_name(|name| name.to_lowercase())
+///   .trim_trailing_newlines()
+///   .cached();
+///
+/// assert_eq!(secrets.get("DB_PASSWORD").as_deref(), Some("hunter2"));
+/// assert_eq!(subst::substitute("postgres://app:${DB_PASSWORD}@db", &secrets).unwrap(), "postgres://app:hunter2@db");
+/// # std::fs::remove_dir_all(&dir)?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// With a [`Router`][super::Router], the files can be read with scheme-prefixed variables:
+/// ```
+/// # fn main() -> std::io::Result<()> {
+/// # let dir = std::env::temp_dir().join(format!("subst-file-dir-router-doc-{}", std::process::id()));
+/// # std::fs::create_dir_all(&dir)?;
+/// use subst::map::{FileDir, Router};
+///
+/// std::fs::write(dir.join("token"), "hunter2")?;
+/// let variables: Router = Router::new().route("file", FileDir::new(&dir));
+///
+/// assert_eq!(subst::substitute("${@file:token}", &variables).unwrap(), "hunter2");
+/// assert!(subst::substitute("${@file:../token}", &variables).is_err());
+/// # std::fs::remove_dir_all(&dir)?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct FileDir<F = fn(&str) -> String> {
+	dir: PathBuf,
+	map_name: F,
+	trim_trailing_newlines: bool,
+	max_size: Option<u64>,
+	file_env: bool,
+	cache: Option<Mutex<HashMap<String, Option<Vec<u8>>>>>,
+}
+
+impl FileDir {
+	/// Create a new map that reads files from the given directory.
+	#[inline]
+	pub fn new(dir: impl Into<PathBuf>) -> Self {
+		Self {
+			dir: dir.into(),
+			map_name: str::to_owned,
+			trim_trailing_newlines: false,
+			max_size: None,
+			file_env: false,
+			cache: None,
+		}
+	}
+}
+
+impl<F> FileDir<F>
+where
+	F: Fn(&str) -> String,
+{
+	/// Use a function to map variable names to file names.
+	///
+	/// By default, the variable name is used as file name.
+	/// If the function returns a name with path separators, or a name that starts with a `.`, the variable is treated as missing.
+	#[inline]
+	pub fn map_name<G>(self, map_name: G) -> FileDir<G>
+	where
+		G: Fn(&str) -> String,
+	{
+		FileDir {
+			dir: self.dir,
+			map_name,
+			trim_trailing_newlines: self.trim_trailing_newlines,
+			max_size: self.max_size,
+			file_env: self.file_env,
+			cache: self.cache,
+		}
+	}
+
+	/// Remove trailing newlines from the file contents.
+	///
+	/// Both `\n` and `\r\n` are removed.
+	#[inline]
+	pub fn trim_trailing_newlines(mut self) -> Self {
+		self.trim_trailing_newlines = true;
+		self
+	}
+
+	/// Limit the size of files that are read.
+	///
+	/// Files larger than `max_size` bytes are treated as missing.
+	#[inline]
+	pub fn max_size(mut self, max_size: u64) -> Self {
+		self.max_size = Some(max_size);
+		self
+	}
+
+	/// Support the `*_FILE` convention.
+	///
+	/// If the environment variable `NAME_FILE` is set, the variable `NAME` is read from the file it points to,
+	/// instead of from the directory.
+	///
+	/// # Example
+	/// ```
+	/// # fn main() -> std::io::Result<()> {
+	/// # let file = std::env::temp_dir().join(format!("subst-file-env-doc-{}", std::process::id()));
+	/// use subst::map::{FileDir, VariableMap};
+	///
+	/// std::fs::write(&file, "s3cr3t\n")?;
+	/// std::env::set_var("API_TOKEN_FILE", &file);
+	///
+	/// let secrets = FileDir::new("/run/secrets").file_env().trim_trailing_newlines();
+	/// assert_eq!(secrets.get("API_TOKEN").as_deref(), Some("s3cr3t"));
+	/// # std::fs::remove_file(&file)?;
+	/// # Ok(())
+	/// # }
+	/// ```
+	#[inline]
+	pub fn file_env(mut self) -> Self {
+		self.file_env = true;
+		self
+	}
+
+	/// Cache the file contents.
+	///
+	/// Each file is read at most once, and missing files are remembered too.
+	/// The cache is shared by all lookups through this map, even from different threads.
+	#[inline]
+	pub fn cached(mut self) -> Self {
+		self.cache = Some(Mutex::new(HashMap::new()));
+		self
+	}
+
+	/// Get the directory to read files from.
+	#[inline]
+	pub fn dir(&self) -> &Path {
+		&self.dir
+	}
+
+	/// Get a map that gives the file contents as byte strings.
+	///
+	/// The byte map shares the configuration and the cache with this map.
+	///
+	/// # Example
+	/// ```
+	/// # fn main() -> std::io::Result<()> {
+	/// # let dir = std::env::temp_dir().join(format!("subst-file-dir-bytes-doc-{}", std::process::id()));
+	/// # std::fs::create_dir_all(&dir)?;
+	/// use subst::map::FileDir;
+	///
+	/// std::fs::write(dir.join("KEY"), b"\xFF\x00")?;
+	/// let secrets = FileDir::new(&dir).max_size(1024);
+	/// let expanded = subst::substitute_bytes(b"key=$KEY", &secrets.bytes()).unwrap();
+	/// assert_eq!(expanded, b"key=\xFF\x00");
+	/// # std::fs::remove_dir_all(&dir)?;
+	/// # Ok(())
+	/// # }
+	/// ```
+	#[inline]
+	pub fn bytes(&self) -> FileDirBytes<'_, F> {
+		FileDirBytes { inner: self }
+	}
+
+	/// Get the path of the file for a variable, if the variable name is valid.
+	///
+	/// This does not check if the file exists.
+	pub fn path(&self, name: &str) -> Option<PathBuf> {
+		if !is_plain_file_name(name) {
+			return None;
+		}
+		if self.file_env {
+			if let Some(path) = std::env::var_os(format!("{name}_FILE")) {
+				return Some(path.into());
+			}
+		}
+		let file_name = (self.map_name)(name);
+		if !is_plain_file_name(&file_name) {
+			return None;
+		}
+		Some(self.dir.join(file_name))
+	}
+
+	/// Read the contents of the file for a variable, using the cache if enabled.
+	fn read(&self, name: &str) -> Option<Vec<u8>> {
+		let Some(cache) = &self.cache else {
+			return self.read_uncached(name);
+		};
+		if let Some(value) = cache.lock().unwrap().get(name) {
+			return value.clone();
+		}
+		let value = self.read_uncached(name);
+		cache.lock().unwrap().insert(name.to_owned(), value.clone());
+		value
+	}
+
+	/// Read the contents of the file for a variable.
+	fn read_uncached(&self, name: &str) -> Option<Vec<u8>> {
+		let path = self.path(name)?;
+		let file = std::fs::File::open(path).ok()?;
+		let mut data = Vec::new();
+		match self.max_size {
+			Some(max_size) => {
+				file.take(max_size.saturating_add(1)).read_to_end(&mut data).ok()?;
+				if data.len() as u64 > max_size {
+					return None;
+				}
+			},
+			None => {
+				(&file).read_to_end(&mut data).ok()?;
+			},
+		}
+		if self.trim_trailing_newlines {
+			while data.ends_with(b"\n") {
+				data.pop();
+				if data.ends_with(b"\r") {
+					data.pop();
+				}
+			}
+		}
+		Some(data)
+	}
+}
+
+impl<F> std::fmt::Debug for FileDir<F> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("FileDir")
+			.field("dir", &self.dir)
+			.field("trim_trailing_newlines", &self.trim_trailing_newlines)
+			.field("max_size", &self.max_size)
+			.field("file_env", &self.file_env)
+			.field("cached", &self.cache.is_some())
+			.finish_non_exhaustive()
+	}
+}
+
+impl<'a, F> VariableMap<'a> for FileDir<F>
+where
+	F: Fn(&str) -> String,
+{
+	type Value = String;
+
+	#[inline]
+	fn get(&'a self, key: &str) -> Option<Self::Value> {
+		String::from_utf8(self.read(key)?).ok()
+	}
+}
+
+/// A map that gives the contents of files in a directory as byte strings.
+///
+/// Created with [`FileDir::bytes()`].
+pub struct FileDirBytes<'a, F = fn(&str) -> String> {
+	inner: &'a FileDir<F>,
+}
+
+impl<F> std::fmt::Debug for FileDirBytes<'_, F> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("FileDirBytes").field("inner", &self.inner).finish()
+	}
+}
+
+impl<F> Clone for FileDirBytes<'_, F> {
+	#[inline]
+	fn clone(&self) -> Self {
+		*self
+	}
+}
+
+impl<F> Copy for FileDirBytes<'_, F> {}
+
+impl<'a, F> VariableMap<'a> for FileDirBytes<'_, F>
+where
+	F: Fn(&str) -> String,
+{
+	type Value = Vec<u8>;
+
+	#[inline]
+	fn get(&'a self, key: &str) -> Option<Self::Value> {
+		self.inner.read(key)
+	}
+}
+
+/// Check if a name can be used as a file name without leaving the directory.
+fn is_plain_file_name(name: &str) -> bool {
+	!name.is_empty() && !name.starts_with('.') && !name.contains(['/', '\\']) && !name.contains('\0')
+}
+
+#[cfg(test)]
+#[rustfmt::skip]
+mod test {
+	use super::*;
+	use crate::test_util::TempDir;
+	use assert2::check;
+
+	#[test]
+	fn test_path() {
+		let dir = TempDir::new("path");
+		let secrets = FileDir::new(dir.path());
+		check!(secrets.path("DB_PASSWORD") == Some(dir.path().join("DB_PASSWORD")));
+		check!(secrets.path("") == None);
+		check!(secrets.path(".hidden") == None);
+		check!(secrets.path("../etc/passwd") == None);
+		check!(secrets.path("a\\b") == None);
+
+		let secrets = FileDir::new(dir.path()).map_name(|name| format!("../{name}"));
+		check!(secrets.path("DB_PASSWORD") == None);
+	}
+
+	#[test]
+	fn test_file_env() {
+		let dir = TempDir::new("file-env");
+		let path = dir.write("token", b"secret");
+		std::env::set_var("SUBST_TEST_FILE_DIR_TOKEN_FILE", &path);
+		std::env::set_var(".SUBST_TEST_FILE_DIR_FILE", &path);
+
+		let secrets = FileDir::new(dir.path());
+		check!(secrets.get("SUBST_TEST_FILE_DIR_TOKEN") == None);
+
+		let secrets = FileDir::new(dir.path()).file_env();
+		check!(secrets.path("SUBST_TEST_FILE_DIR_TOKEN") == Some(path));
+		check!(secrets.get("SUBST_TEST_FILE_DIR_TOKEN").as_deref() == Some("secret"));
+
+		// The variable name is validated before looking at the environment.
+		check!(secrets.path(".SUBST_TEST_FILE_DIR") == None);
+	}
+
+	#[test]
+	fn test_max_size() {
+		let dir = TempDir::new("max-size");
+		dir.write("exact", b"12345");
+		dir.write("larger", b"123456");
+
+		let secrets = FileDir::new(dir.path()).max_size(5);
+		check!(secrets.get("exact").as_deref() == Some("12345"));
+		check!(secrets.get("larger") == None);
+		check!(secrets.bytes().get("larger") == None);
+
+		let secrets = FileDir::new(dir.path()).max_size(0);
+		check!(secrets.get("exact") == None);
+	}
+
+	#[test]
+	fn test_trim_trailing_newlines() {
+		let dir = TempDir::new("trim");
+		dir.write("unix", b"value\n\n");
+		dir.write("windows", b"value\r\n");
+		dir.write("carriage_return", b"value\r");
+		dir.write("inner", b"line 1\r\nline 2\n");
+
+		let secrets = FileDir::new(dir.path());
+		check!(secrets.get("windows").as_deref() == Some("value\r\n"));
+
+		let secrets = FileDir::new(dir.path()).trim_trailing_newlines();
+		check!(secrets.get("unix").as_deref() == Some("value"));
+		check!(secrets.get("windows").as_deref() == Some("value"));
+		check!(secrets.get("carriage_return").as_deref() == Some("value\r"));
+		check!(secrets.get("inner").as_deref() == Some("line 1\r\nline 2"));
+	}
+
+	#[test]
+	fn test_cached() {
+		let dir = TempDir::new("cached");
+		dir.write("value", b"old");
+
+		let uncached = FileDir::new(dir.path());
+		let cached = FileDir::new(dir.path()).cached();
+		check!(cached.get("value").as_deref() == Some("old"));
+		check!(cached.get("missing") == None);
+
+		dir.write("value", b"new");
+		dir.write("missing", b"found");
+		check!(uncached.get("value").as_deref() == Some("new"));
+		check!(cached.get("value").as_deref() == Some("old"));
+		check!(cached.get("missing") == None);
+	}
+
+	#[test]
+	fn test_invalid_utf8() {
+		let dir = TempDir::new("utf8");
+		dir.write("binary", b"\xFF");
+
+		let secrets = FileDir::new(dir.path());
+		check!(secrets.get("binary") == None);
+		check!(secrets.bytes().get("binary").as_deref() == Some(&b"\xFF"[..]));
+	}
+}
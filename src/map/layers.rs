@@ -0,0 +1,182 @@
+use super::VariableMap;
+use crate::error::LookupError;
+
+/// A map that looks up variables in a stack of named layers.
+///
+/// Each variable is looked up in the layers in the order they were added, and the first layer that has the variable wins.
+/// Unlike [`fallback()`][super::fallback], the layers can be maps with different value types.
+/// The values of all layers must implement [`AsRef<T>`], and they are converted to the owned form of `T`.
+/// By default, `T` is [`str`], so the map gives [`String`] values.
+/// Use `Layers<[u8]>` to get [`Vec<u8>`] values for byte templates.
+///
+/// The name of a layer is used to report where the value of a variable came from,
+/// for example to explain the effective configuration to a user.
+/// Use [`Self::layer_of()`] or [`Self::get_with_layer()`] for this.
+///
+/// # Example
+/// ```
+/// # use std::collections::BTreeMap;
+/// use subst::map::{Layers, VariableMap};
+///
+/// let overrides: BTreeMap<String, String> = [("PORT".into(), "9090".into())].into_iter().collect();
+/// let defaults = [("HOST", "localhost"), ("PORT", "8080")];
+///
+/// let variables: Layers = Layers::new()
+///   .layer("overrides", overrides)
+///   .layer("defaults", defaults);
+///
+/// assert_eq!(subst::substitute("$HOST:$PORT", &variables).unwrap(), "localhost:9090");
+/// assert_eq!(variables.layer_of("HOST"), Some("defaults"));
+/// assert_eq!(variables.get_with_layer("PORT"), Some(("overrides", "9090".to_owned())));
+/// assert_eq!(variables.layer_of("USER"), None);
+/// ```
+pub struct Layers<'m, T: ?Sized + ToOwned = str> {
+	layers: Vec<(String, Lookup<'m, T>)>,
+}
+
+/// A type-erased look-up function for a layer.
+type Lookup<'m, T> = Box<dyn Fn(&str) -> Result<Option<<T as ToOwned>::Owned>, LookupError> + 'm>;
+
+impl<'m, T: ?Sized + ToOwned> Layers<'m, T> {
+	/// Create a new map without any layers.
+	#[inline]
+	pub fn new() -> Self {
+		Self { layers: Vec::new() }
+	}
+
+	/// Add a layer below all layers that were added before.
+	///
+	/// The new layer is only used for variables that are not found in any of the previous layers.
+	pub fn layer<M>(mut self, name: impl Into<String>, map: M) -> Self
+	where
+		M: for<'a> VariableMap<'a> + 'm,
+		for<'a> <M as VariableMap<'a>>::Value: AsRef<T>,
+	{
+		let lookup = move |key: &str| Ok(map.lookup(key)?.map(|value| value.as_ref().to_owned()));
+		self.layers.push((name.into(), Box::new(lookup)));
+		self
+	}
+
+	/// Get the names of the layers, from the highest to the lowest priority.
+	pub fn names(&self) -> impl Iterator<Item = &str> {
+		self.layers.iter().map(|(name, _)| name.as_str())
+	}
+
+	/// Get the value of a variable together with the name of the layer that supplied it.
+	///
+	/// If a layer refuses access to the variable, the variable is reported as missing.
+	pub fn get_with_layer(&self, key: &str) -> Option<(&str, T::Owned)> {
+		self.lookup_with_layer(key).ok().flatten()
+	}
+
+	/// Get the value of a variable together with the name of the layer that supplied it,
+	/// or an error if a layer refuses access to the variable.
+	///
+	/// The layers are searched from the highest to the lowest priority,
+	/// and the search stops at the first layer that has the variable or refuses access to it.
+	/// See [`VariableMap::lookup()`].
+	pub fn lookup_with_layer(&self, key: &str) -> Result<Option<(&str, T::Owned)>, LookupError> {
+		for (name, lookup) in &self.layers {
+			if let Some(value) = lookup(key)? {
+				return Ok(Some((name.as_str(), value)));
+			}
+		}
+		Ok(None)
+	}
+
+	/// Get the name of the layer that supplies the value of a variable.
+	///
+	/// This performs a full look-up of the variable and discards the value,
+	/// so it is not cheaper than [`Self::get_with_layer()`].
+	///
+	/// Returns `None` if none of the layers has the variable, or if a layer refuses access to it.
+	pub fn layer_of(&self, key: &str) -> Option<&str> {
+		let (name, _value) = self.lookup_with_layer(key).ok().flatten()?;
+		Some(name)
+	}
+}
+
+impl<T: ?Sized + ToOwned> Default for Layers<'_, T> {
+	#[inline]
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl<T: ?Sized + ToOwned> std::fmt::Debug for Layers<'_, T> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("Layers")
+			.field("names", &self.names().collect::<Vec<_>>())
+			.finish()
+	}
+}
+
+impl<'a, T: ?Sized + ToOwned> VariableMap<'a> for Layers<'_, T> {
+	type Value = T::Owned;
+
+	#[inline]
+	fn get(&'a self, key: &str) -> Option<Self::Value> {
+		let (_name, value) = self.get_with_layer(key)?;
+		Some(value)
+	}
+
+	#[inline]
+	fn lookup(&'a self, key: &str) -> Result<Option<Self::Value>, LookupError> {
+		Ok(self.lookup_with_layer(key)?.map(|(_name, value)| value))
+	}
+}
+
+#[cfg(test)]
+#[rustfmt::skip]
+mod test {
+	use std::collections::BTreeMap;
+
+	use super::*;
+	use crate::map::deny_list;
+	use assert2::{assert, check, let_assert};
+
+	#[test]
+	fn test_layers() {
+		let variables: Layers = Layers::new()
+			.layer("overrides", BTreeMap::from([("PORT".to_owned(), "9090".to_owned())]))
+			.layer("defaults", [("HOST", "localhost"), ("PORT", "8080")]);
+		check!(variables.get("PORT").as_deref() == Some("9090"));
+		check!(variables.get("HOST").as_deref() == Some("localhost"));
+		check!(variables.get("MISSING") == None);
+		check!(variables.names().collect::<Vec<_>>() == ["overrides", "defaults"]);
+		check!(format!("{variables:?}") == r#"Layers { names: ["overrides", "defaults"] }"#);
+
+		check!(variables.layer_of("PORT") == Some("overrides"));
+		check!(variables.layer_of("HOST") == Some("defaults"));
+		check!(variables.layer_of("MISSING") == None);
+		check!(variables.get_with_layer("PORT") == Some(("overrides", "9090".to_owned())));
+		check!(variables.lookup_with_layer("HOST") == Ok(Some(("defaults", "localhost".to_owned()))));
+		check!(variables.lookup_with_layer("MISSING") == Ok(None));
+	}
+
+	#[test]
+	fn test_refused_variable() {
+		// The search stops at the layer that refuses access, so lower layers are not used.
+		let variables: Layers = Layers::new()
+			.layer("secrets", deny_list(BTreeMap::from([("USER", "root")]), ["USER"]))
+			.layer("defaults", [("USER", "nobody")]);
+		let_assert!(Err(LookupError::NotPermitted) = variables.lookup_with_layer("USER"));
+		let_assert!(Err(LookupError::NotPermitted) = variables.lookup("USER"));
+		check!(variables.get("USER") == None);
+		check!(variables.get_with_layer("USER") == None);
+		check!(variables.layer_of("USER") == None);
+
+		let_assert!(Err(e) = crate::substitute("${USER:guest}", &variables));
+		assert!(e.to_string() == "Variable not permitted: $USER");
+	}
+
+	#[test]
+	fn test_byte_layers() {
+		let variables: Layers<[u8]> = Layers::new()
+			.layer("first", BTreeMap::from([("NAME", b"first".to_vec())]))
+			.layer("second", [("NAME", b"second"), ("OTHER", b"other!")]);
+		check!(variables.get("NAME").as_deref() == Some(&b"first"[..]));
+		check!(variables.get("OTHER").as_deref() == Some(&b"other!"[..]));
+		check!(crate::substitute_bytes(b"$NAME $OTHER", &variables).unwrap() == b"first other!");
+	}
+}
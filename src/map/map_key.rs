@@ -0,0 +1,294 @@
+use std::borrow::Cow;
+
+use super::{IterableVariableMap, VariableMap};
+use crate::error::LookupError;
+
+/// [`VariableMap`] produced by [`map_key()`].
+///
+/// By default, the keys of the wrapped map are listed unchanged by [`IterableVariableMap::keys()`].
+/// Use [`Self::reverse()`] to map them back to the names used in templates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct MapKey<M, F, R = ()> {
+	map: M,
+	func: F,
+	reverse: R,
+}
+
+impl<M, F> MapKey<M, F> {
+	/// Add a function to map the keys of the wrapped map back to the names used in templates.
+	///
+	/// The function is used to list the keys with [`IterableVariableMap::keys()`],
+	/// for example to suggest similar variable names in errors.
+	/// If the function returns `None`, the key is not listed.
+	///
+	/// # Example
+	/// ```rust
+	/// # use subst::map::{map_key, IterableVariableMap};
+	/// let variables = [("APP_HOST", "localhost")];
+	/// let variables = map_key(variables, |key| format!("APP_{key}")).reverse(|key| key.strip_prefix("APP_").map(String::from));
+	///
+	/// assert_eq!(variables.keys().collect::<Vec<_>>(), ["HOST"]);
+	/// ```
+	#[inline]
+	pub fn reverse<R>(self, reverse: R) -> MapKey<M, F, R>
+	where
+		R: Fn(&str) -> Option<String>,
+	{
+		MapKey {
+			map: self.map,
+			func: self.func,
+			reverse,
+		}
+	}
+}
+
+impl<'a, M, F, R> VariableMap<'a> for MapKey<M, F, R>
+where
+	M: VariableMap<'a>,
+	F: Fn(&str) -> String,
+{
+	type Value = M::Value;
+
+	#[inline]
+	fn get(&'a self, key: &str) -> Option<Self::Value> {
+		self.map.get(&(self.func)(key))
+	}
+
+	#[inline]
+	fn lookup(&'a self, key: &str) -> Result<Option<Self::Value>, LookupError> {
+		self.map.lookup(&(self.func)(key))
+	}
+}
+
+/// List the keys of the wrapped map unchanged.
+impl<'a, M, F> IterableVariableMap<'a> for MapKey<M, F>
+where
+	M: IterableVariableMap<'a>,
+	F: Fn(&str) -> String,
+{
+	type Keys = M::Keys;
+
+	#[inline]
+	fn keys(&'a self) -> Self::Keys {
+		self.map.keys()
+	}
+}
+
+/// List the keys of the wrapped map, mapped back with the reverse function.
+impl<'a, M, F, R> IterableVariableMap<'a> for MapKey<M, F, R>
+where
+	M: IterableVariableMap<'a>,
+	F: Fn(&str) -> String,
+	R: Fn(&str) -> Option<String> + 'a,
+{
+	type Keys = ReversedKeys<'a, M::Keys, R>;
+
+	#[inline]
+	fn keys(&'a self) -> Self::Keys {
+		ReversedKeys {
+			keys: self.map.keys(),
+			reverse: &self.reverse,
+		}
+	}
+}
+
+/// Iterator over the keys of a [`MapKey`] map with a reverse function.
+#[derive(Debug, Clone)]
+pub struct ReversedKeys<'a, I, R> {
+	keys: I,
+	reverse: &'a R,
+}
+
+impl<'a, I, R> Iterator for ReversedKeys<'a, I, R>
+where
+	I: Iterator<Item = Cow<'a, str>>,
+	R: Fn(&str) -> Option<String>,
+{
+	type Item = Cow<'a, str>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		self.keys.by_ref().find_map(|key| (self.reverse)(&key).map(Cow::Owned))
+	}
+}
+
+/// Creates a [`VariableMap`] that will apply a function to the keys before looking them up in another map.
+///
+/// # Example
+/// ```rust
+/// # use subst::map::{map_key, VariableMap};
+///
+/// let contact_info = [("FIRST_NAME", "John"), ("LAST_NAME", "Doe")];
+///
+/// let contact_info = map_key(contact_info, |key| key.to_uppercase());
+///
+/// assert_eq!(contact_info.get("first_name"), Some(&"John"));
+/// assert_eq!(contact_info.get("Last_Name"), Some(&"Doe"));
+/// assert_eq!(contact_info.get("middle_name"), None);
+/// ```
+pub const fn map_key<M, F>(map: M, func: F) -> MapKey<M, F>
+where
+	F: Fn(&str) -> String,
+{
+	MapKey { map, func, reverse: () }
+}
+
+/// Normalize a variable name to the style of environment variables.
+///
+/// The name is converted to ASCII uppercase, and `-` and `.` are replaced by `_`.
+///
+/// # Example
+/// ```rust
+/// # use subst::map::normalize_key;
+/// assert_eq!(normalize_key("my-var"), "MY_VAR");
+/// assert_eq!(normalize_key("my.var"), "MY_VAR");
+/// assert_eq!(normalize_key("MY_VAR"), "MY_VAR");
+/// ```
+pub fn normalize_key(key: &str) -> String {
+	key.chars()
+		.map(|c| match c {
+			'-' | '.' => '_',
+			c => c.to_ascii_uppercase(),
+		})
+		.collect()
+}
+
+/// Creates a [`VariableMap`] that normalizes keys with [`normalize_key()`] before looking them up in another map.
+///
+/// This allows templates to use names like `my-var` or `my.var` for a variable that is stored as `MY_VAR`.
+///
+/// # Example
+/// ```rust
+/// # use subst::map::{normalized, VariableMap};
+/// let variables = normalized([("DB_HOST", "localhost")]);
+///
+/// assert_eq!(variables.get("db-host"), Some(&"localhost"));
+/// assert_eq!(variables.get("DB_HOST"), Some(&"localhost"));
+/// ```
+pub const fn normalized<M>(map: M) -> MapKey<M, fn(&str) -> String> {
+	map_key(map, normalize_key)
+}
+
+/// [`VariableMap`] produced by [`case_insensitive()`].
+///
+/// See [`case_insensitive()`] for how keys are matched and the cost of a look-up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct CaseInsensitive<M> {
+	map: M,
+}
+
+impl<'a, M> VariableMap<'a> for CaseInsensitive<M>
+where
+	M: IterableVariableMap<'a>,
+{
+	type Value = M::Value;
+
+	fn get(&'a self, key: &str) -> Option<Self::Value> {
+		if let Some(value) = self.map.get(key) {
+			return Some(value);
+		}
+		let key = self.map.keys().find(|candidate| candidate.eq_ignore_ascii_case(key))?;
+		self.map.get(&key)
+	}
+
+	fn lookup(&'a self, key: &str) -> Result<Option<Self::Value>, LookupError> {
+		if let Some(value) = self.map.lookup(key)? {
+			return Ok(Some(value));
+		}
+		match self.map.keys().find(|candidate| candidate.eq_ignore_ascii_case(key)) {
+			Some(key) => self.map.lookup(&key),
+			None => Ok(None),
+		}
+	}
+}
+
+impl<'a, M> IterableVariableMap<'a> for CaseInsensitive<M>
+where
+	M: IterableVariableMap<'a>,
+{
+	type Keys = M::Keys;
+
+	#[inline]
+	fn keys(&'a self) -> Self::Keys {
+		self.map.keys()
+	}
+}
+
+/// Creates a [`VariableMap`] that looks up keys in another map, ignoring ASCII case.
+///
+/// An exact match is always preferred.
+/// Otherwise, the keys of the wrapped map are searched for a key that differs only in ASCII case.
+/// If multiple keys match, the first one listed by the wrapped map is used.
+/// For maps without a fixed order, like a [`HashMap`][std::collections::HashMap], that makes the choice arbitrary.
+///
+/// The keys are listed again for every look-up without an exact match, so such a look-up takes time linear in the number of keys.
+/// This keeps the map up to date with a wrapped map that can change, like [`Env`][super::Env],
+/// but it can be slow for large maps.
+/// If the keys of a large map follow a fixed style, consider [`map_key()`] or [`normalized()`] instead.
+///
+/// # Example
+/// ```rust
+/// # use subst::map::{case_insensitive, VariableMap};
+/// let variables = case_insensitive([("Home", "/home/user"), ("HOME", "/root")]);
+///
+/// assert_eq!(variables.get("HOME"), Some(&"/root"));
+/// assert_eq!(variables.get("home"), Some(&"/home/user"));
+/// assert_eq!(subst::substitute("$home", &variables).unwrap(), "/home/user");
+/// ```
+pub const fn case_insensitive<M>(map: M) -> CaseInsensitive<M> {
+	CaseInsensitive { map }
+}
+
+#[cfg(test)]
+#[rustfmt::skip]
+mod test {
+	use std::collections::BTreeMap;
+
+	use super::*;
+	use crate::map::{deny_list, LookupRecord, Recording};
+	use assert2::{check, let_assert};
+
+	#[test]
+	fn test_case_insensitive() {
+		let variables = case_insensitive([("Home", "/home/user"), ("PATH", "/bin")]);
+		check!(variables.get("Home") == Some(&"/home/user"));
+		check!(variables.get("HOME") == Some(&"/home/user"));
+		check!(variables.get("path") == Some(&"/bin"));
+		check!(variables.get("pAtH") == Some(&"/bin"));
+		check!(variables.get("USER") == None);
+		check!(variables.keys().collect::<Vec<_>>() == ["Home", "PATH"]);
+	}
+
+	#[test]
+	fn test_case_insensitive_collision() {
+		// An exact match wins, otherwise the first key listed by the wrapped map.
+		let variables = case_insensitive(BTreeMap::from([("Home", "mixed"), ("HOME", "upper"), ("home", "lower")]));
+		check!(variables.get("home") == Some(&"lower"));
+		check!(variables.get("HOME") == Some(&"upper"));
+		check!(variables.get("hOmE") == Some(&"upper"));
+
+		let variables = case_insensitive([("home", "lower"), ("HOME", "upper")]);
+		check!(variables.get("Home") == Some(&"lower"));
+	}
+
+	#[test]
+	fn test_case_insensitive_lookups() {
+		// Without an exact match, the wrapped map is searched again with the matching key.
+		let variables = case_insensitive(Recording::new([("HOME", "/root")]));
+		check!(variables.get("HOME") == Some(&"/root"));
+		check!(variables.get("home") == Some(&"/root"));
+		check!(variables.get("user") == None);
+		let lookups = variables.map.lookups();
+		check!(lookups["HOME"] == LookupRecord { hits: 2, misses: 0, denied: 0 });
+		check!(lookups["home"] == LookupRecord { hits: 0, misses: 1, denied: 0 });
+		check!(lookups["user"] == LookupRecord { hits: 0, misses: 1, denied: 0 });
+	}
+
+	#[test]
+	fn test_case_insensitive_lookup_error() {
+		let variables = case_insensitive(deny_list([("SECRET", "hunter2"), ("HOME", "/root")], ["SECRET", "secret"]));
+		let_assert!(Err(LookupError::NotPermitted) = variables.lookup("SECRET"));
+		let_assert!(Err(LookupError::NotPermitted) = variables.lookup("secret"));
+		check!(variables.lookup("home") == Ok(Some(&"/root")));
+		check!(variables.lookup("USER") == Ok(None));
+	}
+}
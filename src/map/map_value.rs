@@ -1,4 +1,5 @@
-use super::VariableMap;
+use super::{IterableVariableMap, VariableMap};
+use crate::error::LookupError;
 
 /// [`VariableMap`] produced by [`map_value()`].
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
@@ -18,6 +19,23 @@ where
 		let value = self.map.get(key)?;
 		Some((self.func)(value))
 	}
+
+	fn lookup(&'a self, key: &str) -> Result<Option<Self::Value>, LookupError> {
+		Ok(self.map.lookup(key)?.map(&self.func))
+	}
+}
+
+impl<'a, M, F, V> IterableVariableMap<'a> for MapValue<M, F>
+where
+	M: IterableVariableMap<'a>,
+	F: Fn(M::Value) -> V,
+{
+	type Keys = M::Keys;
+
+	#[inline]
+	fn keys(&'a self) -> Self::Keys {
+		self.map.keys()
+	}
 }
 
 /// Creates a [`VariableMap`] that will apply a function to the values of another map.
@@ -1,18 +1,50 @@
 //! Maps and related utilities for variable substitution.
 
-use std::borrow::Borrow;
+use std::borrow::{Borrow, Cow};
 use std::collections::{BTreeMap, HashMap};
 use std::hash::BuildHasher;
 
+use crate::error::LookupError;
+
+mod access_list;
+pub use access_list::*;
+
+mod cached;
+pub use cached::*;
+
+mod env_prefixed;
+pub use env_prefixed::*;
+
+mod env_snapshot;
+pub use env_snapshot::*;
+
 mod fallback;
 pub use fallback::*;
 
+mod file_dir;
+pub use file_dir::*;
+
 mod fn_map;
 pub use fn_map::*;
 
+mod layers;
+pub use layers::*;
+
+mod map_key;
+pub use map_key::*;
+
 mod map_value;
 pub use map_value::*;
 
+mod recording;
+pub use recording::*;
+
+mod recursive;
+pub use recursive::*;
+
+mod router;
+pub use router::*;
+
 /// Trait for types that can be used as a variable map.
 pub trait VariableMap<'a> {
 	/// The type returned by the [`get()`][Self::get] function.
@@ -20,6 +52,48 @@ pub trait VariableMap<'a> {
 
 	/// Get a value from the map.
 	fn get(&'a self, key: &str) -> Option<Self::Value>;
+
+	/// Get a value from the map, or an error if the map does not permit access to the variable.
+	///
+	/// This is used to expand templates, so that maps can refuse access to a variable instead of reporting it as missing.
+	/// A refused variable causes expansion to fail with a [`VariableNotPermitted`][crate::error::VariableNotPermitted] error,
+	/// even if the placeholder has a default value.
+	///
+	/// The default implementation never fails, and returns the result of [`Self::get()`].
+	/// Maps that wrap other maps must forward this function to the wrapped map.
+	/// Otherwise, a variable that is refused by the wrapped map is silently reported as missing.
+	#[inline]
+	fn lookup(&'a self, key: &str) -> Result<Option<Self::Value>, LookupError> {
+		Ok(self.get(key))
+	}
+}
+
+/// Trait for variable maps that can list the names of their variables.
+///
+/// This is used to suggest similar variable names when a variable is missing.
+/// See [`Error::with_suggestions()`][crate::Error::with_suggestions].
+pub trait IterableVariableMap<'a>: VariableMap<'a> {
+	/// The iterator returned by the [`keys()`][Self::keys] function.
+	type Keys: Iterator<Item = Cow<'a, str>>;
+
+	/// Get an iterator over the names of all variables in the map.
+	///
+	/// The iterator may produce the same name more than once.
+	fn keys(&'a self) -> Self::Keys;
+}
+
+/// Trait for variable maps that can store new variables.
+///
+/// This is used to assign default values to variables in the form `${NAME:=default}`.
+/// See [`Template::expand_mut()`][crate::Template::expand_mut] and [`ByteTemplate::expand_mut()`][crate::ByteTemplate::expand_mut].
+///
+/// The type parameter is the type of the values that can be stored:
+/// [`String`] for text templates and [`Vec<u8>`] for byte templates.
+pub trait VariableMapMut<T = String> {
+	/// Set the value of a variable in the map.
+	///
+	/// If the variable was already present, the value is replaced.
+	fn set(&mut self, key: &str, value: T);
 }
 
 /// Allow using key-value [`slice`]s as [`VariableMap`]s.
@@ -54,6 +128,20 @@ where
 	}
 }
 
+impl<'a, K, V> IterableVariableMap<'a> for [(K, V)]
+where
+	K: Borrow<str> + 'a,
+	V: 'a,
+{
+	#[allow(clippy::type_complexity)]
+	type Keys = std::iter::Map<std::slice::Iter<'a, (K, V)>, fn(&'a (K, V)) -> Cow<'a, str>>;
+
+	#[inline]
+	fn keys(&'a self) -> Self::Keys {
+		self.iter().map(|(k, _v)| Cow::Borrowed(k.borrow()))
+	}
+}
+
 /// Allow using key-value [`arrays`](`array`) as [`VariableMap`]s.
 ///
 /// Delegate to [impl](#impl-VariableMap<'a>-for-[(K,+V)]) of [`VariableMap`] for [`slices`](`slice`).
@@ -81,6 +169,19 @@ where
 	}
 }
 
+impl<'a, K, V, const N: usize> IterableVariableMap<'a> for [(K, V); N]
+where
+	K: Borrow<str> + 'a,
+	V: 'a,
+{
+	type Keys = <[(K, V)] as IterableVariableMap<'a>>::Keys;
+
+	#[inline(always)]
+	fn keys(&'a self) -> Self::Keys {
+		IterableVariableMap::keys(self.as_slice())
+	}
+}
+
 /// Allow using key-value [`Vec`] as [`VariableMap`]s.
 ///
 /// Delegate to [impl](#impl-VariableMap<'a>-for-[(K,+V)]) of [`VariableMap`] for [`slices`](`slice`).
@@ -108,6 +209,19 @@ where
 	}
 }
 
+impl<'a, K, V> IterableVariableMap<'a> for Vec<(K, V)>
+where
+	K: Borrow<str> + 'a,
+	V: 'a,
+{
+	type Keys = <[(K, V)] as IterableVariableMap<'a>>::Keys;
+
+	#[inline(always)]
+	fn keys(&'a self) -> Self::Keys {
+		IterableVariableMap::keys(self.as_slice())
+	}
+}
+
 impl<'a, T> VariableMap<'a> for &'_ T
 where
 	T: ?Sized + VariableMap<'a>,
@@ -118,6 +232,23 @@ where
 	fn get(&'a self, key: &str) -> Option<Self::Value> {
 		T::get(self, key)
 	}
+
+	#[inline(always)]
+	fn lookup(&'a self, key: &str) -> Result<Option<Self::Value>, LookupError> {
+		T::lookup(self, key)
+	}
+}
+
+impl<'a, T> IterableVariableMap<'a> for &'_ T
+where
+	T: ?Sized + IterableVariableMap<'a>,
+{
+	type Keys = <T as IterableVariableMap<'a>>::Keys;
+
+	#[inline(always)]
+	fn keys(&'a self) -> Self::Keys {
+		T::keys(self)
+	}
 }
 
 impl<'a, T> VariableMap<'a> for &'_ mut T
@@ -130,6 +261,33 @@ where
 	fn get(&'a self, key: &str) -> Option<Self::Value> {
 		T::get(self, key)
 	}
+
+	#[inline(always)]
+	fn lookup(&'a self, key: &str) -> Result<Option<Self::Value>, LookupError> {
+		T::lookup(self, key)
+	}
+}
+
+impl<'a, T> IterableVariableMap<'a> for &'_ mut T
+where
+	T: ?Sized + IterableVariableMap<'a>,
+{
+	type Keys = <T as IterableVariableMap<'a>>::Keys;
+
+	#[inline(always)]
+	fn keys(&'a self) -> Self::Keys {
+		T::keys(self)
+	}
+}
+
+impl<T, V> VariableMapMut<V> for &'_ mut T
+where
+	T: VariableMapMut<V> + ?Sized,
+{
+	#[inline]
+	fn set(&mut self, key: &str, value: V) {
+		(**self).set(key, value)
+	}
 }
 
 impl<'a, T> VariableMap<'a> for std::boxed::Box<T>
@@ -142,6 +300,23 @@ where
 	fn get(&'a self, key: &str) -> Option<Self::Value> {
 		T::get(self, key)
 	}
+
+	#[inline(always)]
+	fn lookup(&'a self, key: &str) -> Result<Option<Self::Value>, LookupError> {
+		T::lookup(self, key)
+	}
+}
+
+impl<'a, T> IterableVariableMap<'a> for std::boxed::Box<T>
+where
+	T: ?Sized + IterableVariableMap<'a>,
+{
+	type Keys = <T as IterableVariableMap<'a>>::Keys;
+
+	#[inline(always)]
+	fn keys(&'a self) -> Self::Keys {
+		T::keys(self)
+	}
 }
 
 impl<'a, T> VariableMap<'a> for std::rc::Rc<T>
@@ -154,6 +329,23 @@ where
 	fn get(&'a self, key: &str) -> Option<Self::Value> {
 		T::get(self, key)
 	}
+
+	#[inline(always)]
+	fn lookup(&'a self, key: &str) -> Result<Option<Self::Value>, LookupError> {
+		T::lookup(self, key)
+	}
+}
+
+impl<'a, T> IterableVariableMap<'a> for std::rc::Rc<T>
+where
+	T: ?Sized + IterableVariableMap<'a>,
+{
+	type Keys = <T as IterableVariableMap<'a>>::Keys;
+
+	#[inline(always)]
+	fn keys(&'a self) -> Self::Keys {
+		T::keys(self)
+	}
 }
 
 impl<'a, T> VariableMap<'a> for std::sync::Arc<T>
@@ -166,6 +358,23 @@ where
 	fn get(&'a self, key: &str) -> Option<Self::Value> {
 		T::get(self, key)
 	}
+
+	#[inline(always)]
+	fn lookup(&'a self, key: &str) -> Result<Option<Self::Value>, LookupError> {
+		T::lookup(self, key)
+	}
+}
+
+impl<'a, T> IterableVariableMap<'a> for std::sync::Arc<T>
+where
+	T: ?Sized + IterableVariableMap<'a>,
+{
+	type Keys = <T as IterableVariableMap<'a>>::Keys;
+
+	#[inline(always)]
+	fn keys(&'a self) -> Self::Keys {
+		T::keys(self)
+	}
 }
 
 /// A "map" that never returns any values.
@@ -181,6 +390,15 @@ impl<'a> VariableMap<'a> for NoSubstitution {
 	}
 }
 
+impl<'a> IterableVariableMap<'a> for NoSubstitution {
+	type Keys = std::iter::Empty<Cow<'a, str>>;
+
+	#[inline]
+	fn keys(&'a self) -> Self::Keys {
+		std::iter::empty()
+	}
+}
+
 /// Value returned by the [`NoSubstitution`] map.
 #[derive(Debug)]
 pub enum NeverValue {}
@@ -205,6 +423,21 @@ impl<'a> VariableMap<'a> for Env {
 	}
 }
 
+/// List the names of all environment variables with a valid Unicode value.
+impl<'a> IterableVariableMap<'a> for Env {
+	#[allow(clippy::type_complexity)]
+	type Keys =
+		std::iter::FilterMap<std::env::VarsOs, fn((std::ffi::OsString, std::ffi::OsString)) -> Option<Cow<'a, str>>>;
+
+	#[inline]
+	fn keys(&'a self) -> Self::Keys {
+		std::env::vars_os().filter_map(|(key, value)| {
+			value.to_str()?;
+			Some(Cow::Owned(key.into_string().ok()?))
+		})
+	}
+}
+
 /// A map that gives byte strings from the environment.
 ///
 /// Only available on Unix platforms.
@@ -224,6 +457,21 @@ impl<'a> VariableMap<'a> for EnvBytes {
 	}
 }
 
+/// List the names of all environment variables.
+///
+/// Variables with a name that is not valid Unicode are skipped.
+#[cfg(unix)]
+impl<'a> IterableVariableMap<'a> for EnvBytes {
+	#[allow(clippy::type_complexity)]
+	type Keys =
+		std::iter::FilterMap<std::env::VarsOs, fn((std::ffi::OsString, std::ffi::OsString)) -> Option<Cow<'a, str>>>;
+
+	#[inline]
+	fn keys(&'a self) -> Self::Keys {
+		std::env::vars_os().filter_map(|(key, _value)| Some(Cow::Owned(key.into_string().ok()?)))
+	}
+}
+
 impl<'a, V: 'a> VariableMap<'a> for BTreeMap<&str, V> {
 	type Value = &'a V;
 
@@ -233,6 +481,16 @@ impl<'a, V: 'a> VariableMap<'a> for BTreeMap<&str, V> {
 	}
 }
 
+impl<'a, 'k: 'a, V: 'a> IterableVariableMap<'a> for BTreeMap<&'k str, V> {
+	#[allow(clippy::type_complexity)]
+	type Keys = std::iter::Map<std::collections::btree_map::Keys<'a, &'k str, V>, fn(&'a &'k str) -> Cow<'a, str>>;
+
+	#[inline]
+	fn keys(&'a self) -> Self::Keys {
+		self.keys().map(|key| Cow::Borrowed(*key))
+	}
+}
+
 impl<'a, V: 'a> VariableMap<'a> for BTreeMap<String, V> {
 	type Value = &'a V;
 
@@ -242,6 +500,23 @@ impl<'a, V: 'a> VariableMap<'a> for BTreeMap<String, V> {
 	}
 }
 
+impl<'a, V: 'a> IterableVariableMap<'a> for BTreeMap<String, V> {
+	#[allow(clippy::type_complexity)]
+	type Keys = std::iter::Map<std::collections::btree_map::Keys<'a, String, V>, fn(&'a String) -> Cow<'a, str>>;
+
+	#[inline]
+	fn keys(&'a self) -> Self::Keys {
+		self.keys().map(|key| Cow::Borrowed(key.as_str()))
+	}
+}
+
+impl<T, V: From<T>> VariableMapMut<T> for BTreeMap<String, V> {
+	#[inline]
+	fn set(&mut self, key: &str, value: T) {
+		self.insert(key.to_owned(), value.into());
+	}
+}
+
 impl<'a, V: 'a, S: BuildHasher> VariableMap<'a> for HashMap<&str, V, S> {
 	type Value = &'a V;
 
@@ -251,6 +526,16 @@ impl<'a, V: 'a, S: BuildHasher> VariableMap<'a> for HashMap<&str, V, S> {
 	}
 }
 
+impl<'a, 'k: 'a, V: 'a, S: BuildHasher> IterableVariableMap<'a> for HashMap<&'k str, V, S> {
+	#[allow(clippy::type_complexity)]
+	type Keys = std::iter::Map<std::collections::hash_map::Keys<'a, &'k str, V>, fn(&'a &'k str) -> Cow<'a, str>>;
+
+	#[inline]
+	fn keys(&'a self) -> Self::Keys {
+		self.keys().map(|key| Cow::Borrowed(*key))
+	}
+}
+
 impl<'a, V: 'a, S: BuildHasher> VariableMap<'a> for HashMap<String, V, S> {
 	type Value = &'a V;
 
@@ -259,3 +544,20 @@ impl<'a, V: 'a, S: BuildHasher> VariableMap<'a> for HashMap<String, V, S> {
 		self.get(key)
 	}
 }
+
+impl<'a, V: 'a, S: BuildHasher> IterableVariableMap<'a> for HashMap<String, V, S> {
+	#[allow(clippy::type_complexity)]
+	type Keys = std::iter::Map<std::collections::hash_map::Keys<'a, String, V>, fn(&'a String) -> Cow<'a, str>>;
+
+	#[inline]
+	fn keys(&'a self) -> Self::Keys {
+		self.keys().map(|key| Cow::Borrowed(key.as_str()))
+	}
+}
+
+impl<T, V: From<T>, S: BuildHasher> VariableMapMut<T> for HashMap<String, V, S> {
+	#[inline]
+	fn set(&mut self, key: &str, value: T) {
+		self.insert(key.to_owned(), value.into());
+	}
+}
@@ -0,0 +1,235 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use super::{IterableVariableMap, VariableMap};
+use crate::error::LookupError;
+
+/// A map that records all lookups in another map.
+///
+/// For every key that is looked up, the map records how often it was found, how often it was missing,
+/// and how often the wrapped map refused access to it.
+/// The recorded lookups can be inspected after expansion, for example to audit which environment variables were used,
+/// or to find variables in a map that are never used by any template.
+///
+/// The recording is protected by a mutex, so the map can be shared between threads.
+///
+/// # Example
+/// ```
+/// # use std::collections::BTreeMap;
+/// use subst::map::{LookupRecord, Recording};
+///
+/// let variables: BTreeMap<&str, &str> = [("HOST", "localhost"), ("PORT", "8080"), ("USER", "app")].into_iter().collect();
+/// let variables = Recording::new(variables);
+///
+/// let url = subst::substitute("http://$HOST:$PORT/${PATH:}", &variables).unwrap();
+/// assert_eq!(url, "http://localhost:8080/");
+///
+/// assert_eq!(variables.lookups()["HOST"], LookupRecord { hits: 1, misses: 0, denied: 0 });
+/// assert_eq!(variables.found_keys(), ["HOST", "PORT"]);
+/// assert_eq!(variables.missing_keys(), ["PATH"]);
+/// assert_eq!(variables.unused_keys(), ["USER"]);
+/// ```
+#[derive(Debug, Default)]
+pub struct Recording<M> {
+	map: M,
+	lookups: Mutex<BTreeMap<String, LookupRecord>>,
+}
+
+/// The recorded lookups of a single key in a [`Recording`] map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct LookupRecord {
+	/// The number of lookups that found the key.
+	pub hits: usize,
+
+	/// The number of lookups that did not find the key.
+	pub misses: usize,
+
+	/// The number of lookups that were refused by the wrapped map.
+	///
+	/// See [`VariableMap::lookup()`].
+	pub denied: usize,
+}
+
+impl LookupRecord {
+	/// Get the total number of lookups of the key.
+	#[inline]
+	pub fn count(&self) -> usize {
+		self.hits + self.misses + self.denied
+	}
+
+	/// Check if the key was found by at least one lookup.
+	#[inline]
+	pub fn found(&self) -> bool {
+		self.hits > 0
+	}
+}
+
+impl<M> Recording<M> {
+	/// Create a new map that records all lookups in the given map.
+	#[inline]
+	pub fn new(map: M) -> Self {
+		Self {
+			map,
+			lookups: Mutex::new(BTreeMap::new()),
+		}
+	}
+
+	/// Get a reference to the wrapped map.
+	#[inline]
+	pub fn inner(&self) -> &M {
+		&self.map
+	}
+
+	/// Consume the recording map and return the wrapped map.
+	#[inline]
+	pub fn into_inner(self) -> M {
+		self.map
+	}
+
+	/// Get all recorded lookups, ordered by key.
+	pub fn lookups(&self) -> BTreeMap<String, LookupRecord> {
+		self.lookups.lock().unwrap().clone()
+	}
+
+	/// Get the keys that were found by at least one lookup, ordered by key.
+	pub fn found_keys(&self) -> Vec<String> {
+		self.keys_where(|record| record.found())
+	}
+
+	/// Get the keys that were never found by any lookup, ordered by key.
+	pub fn missing_keys(&self) -> Vec<String> {
+		self.keys_where(|record| !record.found())
+	}
+
+	/// Get the keys for which the wrapped map refused access at least once, ordered by key.
+	pub fn denied_keys(&self) -> Vec<String> {
+		self.keys_where(|record| record.denied > 0)
+	}
+
+	/// Forget all recorded lookups.
+	pub fn clear(&self) {
+		self.lookups.lock().unwrap().clear();
+	}
+
+	/// Update the record for a key.
+	fn record(&self, key: &str, update: impl FnOnce(&mut LookupRecord)) {
+		let mut lookups = self.lookups.lock().unwrap();
+		match lookups.get_mut(key) {
+			Some(record) => update(record),
+			None => update(lookups.entry(key.to_owned()).or_default()),
+		}
+	}
+
+	/// Get the recorded keys that match a predicate, ordered by key.
+	fn keys_where(&self, predicate: impl Fn(&LookupRecord) -> bool) -> Vec<String> {
+		self.lookups
+			.lock()
+			.unwrap()
+			.iter()
+			.filter(|(_key, record)| predicate(record))
+			.map(|(key, _record)| key.clone())
+			.collect()
+	}
+
+	/// Get the keys of the wrapped map that were never looked up, ordered by key.
+	///
+	/// Listing the keys of the wrapped map does not count as a lookup.
+	pub fn unused_keys<'a>(&'a self) -> Vec<String>
+	where
+		M: IterableVariableMap<'a>,
+	{
+		let lookups = self.lookups.lock().unwrap();
+		let mut unused: Vec<String> = self
+			.map
+			.keys()
+			.filter(|key| !lookups.contains_key(key.as_ref()))
+			.map(|key| key.into_owned())
+			.collect();
+		unused.sort();
+		unused.dedup();
+		unused
+	}
+}
+
+impl<'a, M> VariableMap<'a> for Recording<M>
+where
+	M: VariableMap<'a>,
+{
+	type Value = M::Value;
+
+	#[inline]
+	fn get(&'a self, key: &str) -> Option<Self::Value> {
+		self.lookup(key).ok().flatten()
+	}
+
+	fn lookup(&'a self, key: &str) -> Result<Option<Self::Value>, LookupError> {
+		let value = self.map.lookup(key);
+		self.record(key, |record| match value {
+			Ok(Some(_)) => record.hits += 1,
+			Ok(None) => record.misses += 1,
+			Err(_) => record.denied += 1,
+		});
+		value
+	}
+}
+
+/// List the keys of the wrapped map, without recording a lookup.
+impl<'a, M> IterableVariableMap<'a> for Recording<M>
+where
+	M: IterableVariableMap<'a>,
+{
+	type Keys = M::Keys;
+
+	#[inline]
+	fn keys(&'a self) -> Self::Keys {
+		self.map.keys()
+	}
+}
+
+#[cfg(test)]
+#[rustfmt::skip]
+mod test {
+	use std::collections::BTreeMap;
+
+	use super::*;
+	use crate::map::deny_list;
+	use assert2::{check, let_assert};
+
+	#[test]
+	fn test_get_and_lookup_record_the_same() {
+		let variables = Recording::new(deny_list(BTreeMap::from([("HOST", "localhost"), ("SECRET", "hunter2")]), ["SECRET"]));
+		check!(variables.get("HOST") == Some(&"localhost"));
+		check!(variables.get("PORT") == None);
+		check!(variables.get("SECRET") == None);
+		check!(variables.lookups() == BTreeMap::from([
+			("HOST".into(), LookupRecord { hits: 1, misses: 0, denied: 0 }),
+			("PORT".into(), LookupRecord { hits: 0, misses: 1, denied: 0 }),
+			("SECRET".into(), LookupRecord { hits: 0, misses: 0, denied: 1 }),
+		]));
+
+		variables.clear();
+		check!(variables.lookup("HOST") == Ok(Some(&"localhost")));
+		check!(variables.lookup("PORT") == Ok(None));
+		let_assert!(Err(LookupError::NotPermitted) = variables.lookup("SECRET"));
+		check!(variables.lookups() == BTreeMap::from([
+			("HOST".into(), LookupRecord { hits: 1, misses: 0, denied: 0 }),
+			("PORT".into(), LookupRecord { hits: 0, misses: 1, denied: 0 }),
+			("SECRET".into(), LookupRecord { hits: 0, misses: 0, denied: 1 }),
+		]));
+	}
+
+	#[test]
+	fn test_keys() {
+		let variables = Recording::new(deny_list(BTreeMap::from([("HOST", "localhost"), ("PORT", "80"), ("SECRET", "hunter2"), ("USER", "app")]), ["SECRET"]));
+		check!(crate::substitute("$HOST:${PORT} ${PATH:/} ${SECRET:none}", &variables).is_err());
+		check!(variables.found_keys() == ["HOST", "PORT"]);
+		check!(variables.missing_keys() == ["PATH", "SECRET"]);
+		check!(variables.denied_keys() == ["SECRET"]);
+		check!(variables.unused_keys() == ["USER"]);
+		check!(variables.lookups()["HOST"].count() == 1);
+
+		// Listing the keys is not a lookup.
+		check!(variables.keys().count() == 3);
+		check!(variables.lookups().len() == 4);
+	}
+}
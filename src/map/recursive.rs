@@ -0,0 +1,243 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use super::{IterableVariableMap, VariableMap};
+use crate::error::{ExpandError, LookupError};
+use crate::Template;
+
+/// A map where the values are templates that are expanded with the same map.
+///
+/// Each value from the wrapped map is parsed as a template, and the variables in it are looked up in this map again.
+/// This allows variables to be defined in terms of each other, in any order,
+/// like `BASE=/opt/app` and `LOG=${BASE}/log`.
+///
+/// The expanded values are remembered, so every variable is expanded at most once.
+/// The cache is protected by a mutex, so the map can be shared between threads.
+///
+/// If the value of a variable refers back to the variable itself, directly or through other variables,
+/// the lookup fails with [`LookupError::Cycle`].
+/// When expanding a template, this results in a [`VariableCycle`][crate::error::VariableCycle] error that lists the whole cycle.
+///
+/// If a value is not a valid template, or if it refers to a variable that does not exist,
+/// the variable is treated as missing, and this is remembered like any other value.
+/// This means that the default value of the variable is used, if it has one.
+/// If it does not, the error names the variable from the template, not the inner variable that is missing:
+/// with `LOG=${BASE}/log` and no `BASE`, expanding `$LOG` fails with "No such variable: $LOG".
+///
+/// # Example
+/// ```
+/// # use std::collections::BTreeMap;
+/// use subst::map::{Recursive, VariableMap};
+///
+/// let variables: BTreeMap<&str, &str> = [
+///   ("LOG", "${BASE}/log"),
+///   ("BASE", "/opt/app"),
+///   ("A", "$B"),
+///   ("B", "${C:$A}"),
+/// ].into_iter().collect();
+/// let variables = Recursive::new(variables);
+///
+/// assert_eq!(variables.get("LOG").as_deref(), Some("/opt/app/log"));
+/// assert_eq!(subst::substitute("$LOG/app.log", &variables).unwrap(), "/opt/app/log/app.log");
+///
+/// let error = subst::substitute("$A", &variables).unwrap_err();
+/// assert_eq!(error.to_string(), "Variable cycle: $A -> $B -> $A");
+/// ```
+#[derive(Debug, Default)]
+pub struct Recursive<M> {
+	map: M,
+	cache: Mutex<HashMap<String, Option<String>>>,
+}
+
+impl<M> Recursive<M> {
+	/// Create a new map that expands the values of the given map.
+	#[inline]
+	pub fn new(map: M) -> Self {
+		Self {
+			map,
+			cache: Mutex::new(HashMap::new()),
+		}
+	}
+
+	/// Get a reference to the wrapped map.
+	#[inline]
+	pub fn inner(&self) -> &M {
+		&self.map
+	}
+
+	/// Consume the map and return the wrapped map.
+	#[inline]
+	pub fn into_inner(self) -> M {
+		self.map
+	}
+
+	/// Forget all expanded values, so that they are expanded again on the next lookup.
+	pub fn clear(&self) {
+		self.cache.lock().unwrap().clear();
+	}
+}
+
+impl<'a, M> VariableMap<'a> for Recursive<M>
+where
+	M: VariableMap<'a>,
+	M::Value: AsRef<str>,
+{
+	type Value = String;
+
+	#[inline]
+	fn get(&'a self, key: &str) -> Option<Self::Value> {
+		self.lookup(key).ok().flatten()
+	}
+
+	fn lookup(&'a self, key: &str) -> Result<Option<Self::Value>, LookupError> {
+		let resolver = Resolver {
+			recursive: self,
+			stack: RefCell::new(Vec::new()),
+		};
+		resolver.resolve(key)
+	}
+}
+
+/// List the keys of the wrapped map.
+impl<'a, M> IterableVariableMap<'a> for Recursive<M>
+where
+	M: IterableVariableMap<'a>,
+	M::Value: AsRef<str>,
+{
+	type Keys = M::Keys;
+
+	#[inline]
+	fn keys(&'a self) -> Self::Keys {
+		self.map.keys()
+	}
+}
+
+/// The state of a single lookup in a [`Recursive`] map.
+///
+/// Used as variable map to expand the values, so that the variables that are being expanded can be tracked to detect cycles.
+struct Resolver<'r, M> {
+	/// The map to resolve variables with.
+	recursive: &'r Recursive<M>,
+
+	/// The variables that are currently being expanded, outermost first.
+	stack: RefCell<Vec<String>>,
+}
+
+impl<'r, M> Resolver<'r, M>
+where
+	M: VariableMap<'r>,
+	M::Value: AsRef<str>,
+{
+	/// Look up and expand a variable.
+	fn resolve(&self, key: &str) -> Result<Option<String>, LookupError> {
+		if let Some(value) = self.recursive.cache.lock().unwrap().get(key) {
+			return Ok(value.clone());
+		}
+		if let Some(start) = self.stack.borrow().iter().position(|name| name == key) {
+			let mut cycle = self.stack.borrow()[start..].to_vec();
+			cycle.push(key.to_owned());
+			return Err(LookupError::Cycle(cycle));
+		}
+
+		let value = match self.recursive.map.lookup(key)? {
+			Some(raw) => self.expand(key, raw.as_ref())?,
+			None => None,
+		};
+		self.recursive
+			.cache
+			.lock()
+			.unwrap()
+			.insert(key.to_owned(), value.clone());
+		Ok(value)
+	}
+
+	/// Expand the raw value of a variable.
+	fn expand(&self, key: &str, raw: &str) -> Result<Option<String>, LookupError> {
+		let Ok(template) = Template::from_str(raw) else {
+			return Ok(None);
+		};
+		self.stack.borrow_mut().push(key.to_owned());
+		let value = template.expand(self);
+		self.stack.borrow_mut().pop();
+		match value {
+			Ok(value) => Ok(Some(value)),
+			Err(ExpandError::NotPermitted(_)) => Err(LookupError::NotPermitted),
+			Err(ExpandError::Cycle(e)) => Err(LookupError::Cycle(e.cycle)),
+			Err(_) => Ok(None),
+		}
+	}
+}
+
+impl<'b, 'r, M> VariableMap<'b> for Resolver<'r, M>
+where
+	M: VariableMap<'r>,
+	M::Value: AsRef<str>,
+{
+	type Value = String;
+
+	#[inline]
+	fn get(&'b self, key: &str) -> Option<Self::Value> {
+		self.resolve(key).ok().flatten()
+	}
+
+	#[inline]
+	fn lookup(&'b self, key: &str) -> Result<Option<Self::Value>, LookupError> {
+		self.resolve(key)
+	}
+}
+
+#[cfg(test)]
+#[rustfmt::skip]
+mod test {
+	use std::collections::BTreeMap;
+
+	use super::*;
+	use crate::map::Recording;
+	use assert2::{check, let_assert};
+
+	#[test]
+	fn test_diamond_is_expanded_once() {
+		let variables = Recursive::new(Recording::new(BTreeMap::from([("BASE", "/opt"), ("A", "$BASE/a"), ("B", "$BASE/b"), ("ALL", "$A:$B")])));
+		check!(variables.get("ALL").as_deref() == Some("/opt/a:/opt/b"));
+		check!(variables.get("A").as_deref() == Some("/opt/a"));
+		check!(variables.inner().lookups()["BASE"].count() == 1);
+		check!(variables.inner().lookups()["A"].count() == 1);
+
+		variables.clear();
+		check!(variables.get("A").as_deref() == Some("/opt/a"));
+		check!(variables.inner().lookups()["A"].count() == 2);
+		check!(variables.inner().lookups()["BASE"].count() == 2);
+	}
+
+	#[test]
+	fn test_cycle() {
+		let variables = Recursive::new([("A", "x$A")]);
+		let_assert!(Err(LookupError::Cycle(cycle)) = variables.lookup("A"));
+		check!(cycle == ["A", "A"]);
+		check!(variables.get("A") == None);
+
+		// Cycles through default values are detected too.
+		let variables = Recursive::new([("A", "${X:$B}"), ("B", "$A")]);
+		let_assert!(Err(LookupError::Cycle(cycle)) = variables.lookup("A"));
+		check!(cycle == ["A", "B", "A"]);
+
+		let_assert!(Err(crate::Error::Cycle(e)) = crate::substitute("${B:fallback}", &variables));
+		check!(e.cycle == ["B", "A", "B"]);
+	}
+
+	#[test]
+	fn test_invalid_values_are_missing() {
+		let variables = Recursive::new(Recording::new(BTreeMap::from([("INVALID", "${"), ("LOG", "${BASE}/log")])));
+		check!(let Ok(None) = variables.lookup("INVALID"));
+		check!(let Ok(None) = variables.lookup("LOG"));
+		check!(crate::substitute("${LOG:/var/log}", &variables).as_deref() == Ok("/var/log"));
+
+		let_assert!(Err(e) = crate::substitute("$LOG", &variables));
+		check!(e.to_string() == "No such variable: $LOG");
+
+		// The missing values are remembered too.
+		check!(variables.inner().lookups()["LOG"].count() == 1);
+		check!(variables.inner().lookups()["BASE"].count() == 1);
+	}
+}
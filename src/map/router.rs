@@ -0,0 +1,188 @@
+use super::VariableMap;
+use crate::error::LookupError;
+
+/// A map that dispatches scheme-prefixed variables to a different map for each scheme.
+///
+/// Templates can refer to scheme-prefixed variables as `${@scheme:key}` or `${@scheme:key:default}`.
+/// The `@` sets them apart from normal variables, just like the `${@include path}` directive.
+/// The key can contain any character except whitespace, `$`, `{`, `}`, `:` and `\`,
+/// so it can hold things like file paths.
+/// The special characters can be escaped with a backslash, like `${@url:http\://localhost}`.
+///
+/// The `include` scheme is best avoided:
+/// `${@include:key}` is a scheme-prefixed variable, but `${@include key}` is an include directive.
+///
+/// A scheme-prefixed variable is looked up by the name `scheme:key`.
+/// The router splits the name at the first colon, and looks up the key in the map for the scheme.
+/// Variables without a scheme are looked up in the map set with [`Self::unscoped()`], if any.
+///
+/// The maps for the different schemes can be of different types.
+/// Their values are converted to the value type of the router with [`Into`].
+///
+/// # Example
+/// ```
+/// # use std::collections::HashMap;
+/// # use subst::map::{from_fn, Router};
+/// # std::env::set_var("HOME", "/home/user");
+/// let variables: Router = Router::new()
+///   .route("env", subst::Env)
+///   .route("file", from_fn(|path| std::fs::read_to_string(path).ok()))
+///   .route("literal", from_fn(|value| Some(value.to_owned())))
+///   .unscoped(HashMap::from([("app".to_owned(), "my-app".to_owned())]));
+///
+/// assert_eq!(
+///   subst::substitute("${@env:HOME}/.config/$app", &variables).unwrap(),
+///   "/home/user/.config/my-app",
+/// );
+/// assert_eq!(
+///   subst::substitute("${@file:/does/not/exist:${@literal:none}}", &variables).unwrap(),
+///   "none",
+/// );
+/// ```
+pub struct Router<'m, V = String> {
+	routes: Vec<(String, Lookup<'m, V>)>,
+	unscoped: Option<Lookup<'m, V>>,
+}
+
+/// A type-erased look-up function for a map.
+type Lookup<'m, V> = Box<dyn Fn(&str) -> Result<Option<V>, LookupError> + 'm>;
+
+impl<'m, V> Router<'m, V> {
+	/// Create a new router without any schemes.
+	#[inline]
+	pub fn new() -> Self {
+		Self {
+			routes: Vec::new(),
+			unscoped: None,
+		}
+	}
+
+	/// Add a map for a scheme.
+	///
+	/// If a map was already added for the scheme, it is replaced.
+	///
+	/// A scheme can only contain ASCII letters, digits and underscores, or it can not be used in a template.
+	pub fn route<M>(mut self, scheme: impl Into<String>, map: M) -> Self
+	where
+		M: for<'a> VariableMap<'a> + 'm,
+		for<'a> <M as VariableMap<'a>>::Value: Into<V>,
+	{
+		let scheme = scheme.into();
+		let lookup: Lookup<'m, V> = Box::new(move |key| Ok(map.lookup(key)?.map(Into::into)));
+		match self.routes.iter_mut().find(|(name, _)| *name == scheme) {
+			Some((_, route)) => *route = lookup,
+			None => self.routes.push((scheme, lookup)),
+		}
+		self
+	}
+
+	/// Set the map for variables without a scheme.
+	///
+	/// Without this map, variables without a scheme are never found.
+	pub fn unscoped<M>(mut self, map: M) -> Self
+	where
+		M: for<'a> VariableMap<'a> + 'm,
+		for<'a> <M as VariableMap<'a>>::Value: Into<V>,
+	{
+		self.unscoped = Some(Box::new(move |key| Ok(map.lookup(key)?.map(Into::into))));
+		self
+	}
+
+	/// Get the schemes that the router has a map for, in the order they were added.
+	pub fn schemes(&self) -> impl Iterator<Item = &str> {
+		self.routes.iter().map(|(scheme, _)| scheme.as_str())
+	}
+}
+
+impl<V> Default for Router<'_, V> {
+	#[inline]
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl<V> std::fmt::Debug for Router<'_, V> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("Router")
+			.field("schemes", &self.schemes().collect::<Vec<_>>())
+			.field("unscoped", &self.unscoped.is_some())
+			.finish()
+	}
+}
+
+impl<'a, V> VariableMap<'a> for Router<'_, V> {
+	type Value = V;
+
+	#[inline]
+	fn get(&'a self, key: &str) -> Option<Self::Value> {
+		self.lookup(key).ok().flatten()
+	}
+
+	fn lookup(&'a self, key: &str) -> Result<Option<Self::Value>, LookupError> {
+		let (lookup, key) = match key.split_once(':') {
+			Some((scheme, key)) => (
+				self.routes
+					.iter()
+					.find(|(name, _)| name == scheme)
+					.map(|(_, lookup)| lookup),
+				key,
+			),
+			None => (self.unscoped.as_ref(), key),
+		};
+		match lookup {
+			Some(lookup) => lookup(key),
+			None => Ok(None),
+		}
+	}
+}
+
+#[cfg(test)]
+#[rustfmt::skip]
+mod test {
+	use std::collections::BTreeMap;
+
+	use super::*;
+	use crate::map::{deny_list, from_fn};
+	use assert2::{assert, check, let_assert};
+
+	#[test]
+	fn test_route() {
+		let variables: Router = Router::new()
+			.route("a", BTreeMap::from([("name".to_owned(), "from a".to_owned())]))
+			.route("b", BTreeMap::from([("name".to_owned(), "from b".to_owned()), ("key:with:colons".to_owned(), "colons".to_owned())]));
+		check!(variables.get("a:name").as_deref() == Some("from a"));
+		check!(variables.get("b:name").as_deref() == Some("from b"));
+		check!(variables.get("b:key:with:colons").as_deref() == Some("colons"));
+		check!(variables.get("a:other") == None);
+		check!(variables.get("c:name") == None);
+		check!(variables.get("name") == None);
+		check!(variables.schemes().collect::<Vec<_>>() == ["a", "b"]);
+
+		// Adding a map for an existing scheme replaces it, and keeps the order of the schemes.
+		let variables = variables.route("a", from_fn(|_key| Some("replaced")));
+		check!(variables.get("a:name").as_deref() == Some("replaced"));
+		check!(variables.schemes().collect::<Vec<_>>() == ["a", "b"]);
+	}
+
+	#[test]
+	fn test_unscoped() {
+		let variables: Router = Router::new()
+			.route("a", from_fn(|_key| Some("scoped")))
+			.unscoped(BTreeMap::from([("name".to_owned(), "unscoped".to_owned()), ("a:name".to_owned(), "not used".to_owned())]));
+		check!(variables.get("name").as_deref() == Some("unscoped"));
+		check!(variables.get("a:name").as_deref() == Some("scoped"));
+		check!(variables.get("b:name") == None);
+		check!(format!("{variables:?}") == r#"Router { schemes: ["a"], unscoped: true }"#);
+	}
+
+	#[test]
+	fn test_lookup_error() {
+		let variables: Router = Router::new()
+			.route("a", deny_list(BTreeMap::from([("secret".to_owned(), "hunter2".to_owned())]), ["secret"]));
+		let_assert!(Err(LookupError::NotPermitted) = variables.lookup("a:secret"));
+		check!(variables.get("a:secret") == None);
+
+		let_assert!(Err(e) = crate::substitute("${@a:secret:default}", &variables));
+		assert!(e.to_string() == "Variable not permitted: ${@a:secret}");
+	}
+}
@@ -1,11 +1,23 @@
+
 use core::pin::Pin;
 
-use crate::VariableMap;
 use crate::error::{ExpandError, ParseError};
 use crate::non_aliasing::NonAliasing;
+use crate::{IncludeResolver, VariableMap, VariableMapMut};
 
 mod raw;
 
+/// Find the variables with a default value that contains a position in the source.
+///
+/// Returns the range from the dollar sign up to and including the colon (or `:=`) for each variable,
+/// from the outermost to the innermost.
+/// Syntax errors are recovered from, so this also works for the position of a parse error.
+#[cfg(feature = "codespan-reporting")]
+pub(crate) fn enclosing_defaults(source: &[u8], position: usize) -> Vec<std::ops::Range<usize>> {
+	let (template, _errors) = raw::Template::parse_recovering(source, 0, raw::ParseContext::new());
+	template.enclosing_defaults(position)
+}
+
 /// A parsed string template that borrows the source string.
 ///
 /// You can parse the template once and call [`Self::expand()`] multiple times.
@@ -35,20 +47,74 @@ impl<'a> Template<'a> {
 	/// The source is can contain variables to be substituted later,
 	/// when you call [`Self::expand()`].
 	///
-	/// Variables have the form `$NAME`, `${NAME}` or `${NAME:default}`.
+	/// Variables have the form `$NAME`, `${NAME}`, `${NAME:default}` or `${NAME:=default}`.
+	/// The last form assigns the default value to the variable when expanded with [`Self::expand_mut()`].
 	/// A variable name can only consist of ASCII letters, digits and underscores.
 	/// They are allowed to start with numbers.
 	///
-	/// You can escape dollar signs, backslashes, colons and braces with a backslash.
+	/// You can escape dollar signs, backslashes, colons, equals signs and braces with a backslash.
 	#[inline]
 	#[allow(clippy::should_implement_trait)]
 	pub fn from_str(source: &'a str) -> Result<Self, ParseError> {
 		Ok(Self {
 			source,
-			raw: raw::Template::parse(source.as_bytes(), 0)?,
+			raw: raw::Template::parse(source.as_bytes(), 0, &mut raw::ParseContext::new())?,
 		})
 	}
 
+	/// Parse a template from a string slice, with support for include directives.
+	///
+	/// This works the same as [`Self::from_str()`],
+	/// except that include directives in the form `${@include path}` are resolved using the given resolver.
+	/// The `root_id` is the ID of the template itself, if it has one.
+	/// See the [`include`][crate::include] module for more details.
+	#[inline]
+	pub fn from_str_with_includes<R>(source: &'a str, resolver: &R, root_id: Option<&str>) -> Result<Self, ParseError>
+	where
+		R: IncludeResolver + ?Sized,
+	{
+		Ok(Self {
+			source,
+			raw: raw::Template::parse(
+				source.as_bytes(),
+				0,
+				&mut raw::ParseContext::with_includes(&resolver, root_id),
+			)?,
+		})
+	}
+
+	/// Parse a template from a string slice, recovering from syntax errors.
+	///
+	/// Instead of stopping at the first error, this reports all syntax errors in the source,
+	/// together with a best-effort template.
+	/// In the best-effort template, invalid escape sequences and dollar signs that do not start a variable are kept as literal text,
+	/// and variable placeholders without a closing brace extend to the end of the source.
+	///
+	/// If no errors are reported, the template is identical to the one returned by [`Self::from_str()`].
+	#[inline]
+	pub fn from_str_recovering(source: &'a str) -> (Self, Vec<ParseError>) {
+		let (raw, errors) = raw::Template::parse_recovering(source.as_bytes(), 0, raw::ParseContext::new());
+		(Self { source, raw }, errors)
+	}
+
+	/// Parse a template from a string slice with support for include directives, recovering from syntax errors.
+	///
+	/// This combines [`Self::from_str_with_includes()`] and [`Self::from_str_recovering()`].
+	/// Errors in included templates are reported as [`ParseError::Include`] errors.
+	#[inline]
+	pub fn from_str_with_includes_recovering<R>(
+		source: &'a str,
+		resolver: &R,
+		root_id: Option<&str>,
+	) -> (Self, Vec<ParseError>)
+	where
+		R: IncludeResolver + ?Sized,
+	{
+		let context = raw::ParseContext::with_includes(&resolver, root_id);
+		let (raw, errors) = raw::Template::parse_recovering(source.as_bytes(), 0, context);
+		(Self { source, raw }, errors)
+	}
+
 	/// Get the original source string.
 	#[inline]
 	pub fn source(&self) -> &str {
@@ -74,6 +140,36 @@ impl<'a> Template<'a> {
 		unsafe { Ok(String::from_utf8_unchecked(output)) }
 	}
 
+	/// Expand the template, and assign default values to variables in the form `${NAME:=default}`.
+	///
+	/// This works like [`Self::expand()`], but if a variable with the form `${NAME:=default}` is missing from the map,
+	/// the expanded default value is also stored in the map with [`VariableMapMut::set()`].
+	/// Later occurrences of the variable in the same template, and later templates expanded with the same map, will use the stored value.
+	///
+	/// For a scheme-prefixed variable like `${@env:HOME:=/root}`, the value is stored under the full name `env:HOME`.
+	/// This is also the name that is used to look up the variable, so later occurrences find the stored value.
+	///
+	/// # Example
+	/// ```
+	/// # use std::collections::BTreeMap;
+	/// # use subst::Template;
+	/// let mut variables: BTreeMap<String, String> = BTreeMap::new();
+	///
+	/// let template = Template::from_str("${NAME:=world}, hello $NAME!").unwrap();
+	/// assert_eq!(template.expand_mut(&mut variables).unwrap(), "world, hello world!");
+	/// assert_eq!(variables["NAME"], "world");
+	/// ```
+	pub fn expand_mut<M>(&self, variables: &mut M) -> Result<String, ExpandError>
+	where
+		M: VariableMapMut + for<'b> VariableMap<'b> + ?Sized,
+		for<'b> <M as VariableMap<'b>>::Value: AsRef<str>,
+	{
+		let mut output = Vec::with_capacity(self.source.len() + self.source.len() / 10);
+		self.raw.expand_mut(&mut output, self.source.as_bytes(), variables)?;
+		// SAFETY: Both source and all variable values are valid UTF-8, so substitation result is also valid UTF-8.
+		unsafe { Ok(String::from_utf8_unchecked(output)) }
+	}
+
 	/// Transmute the lifetime of the source data.
 	///
 	/// # Safety:
@@ -111,7 +207,7 @@ impl Clone for TemplateBuf {
 
 		let template = Template {
 			raw,
-			source: &*source,
+			source: &source,
 		};
 		// SAFETY: The str slice given to `template` must remain valid.
 		// Since `String` keeps data on the heap, it remains valid when the `source` is moved.
@@ -142,15 +238,38 @@ impl TemplateBuf {
 	/// The source is can contain variables to be substituted later,
 	/// when you call [`Self::expand()`].
 	///
-	/// Variables have the form `$NAME`, `${NAME}` or `${NAME:default}`.
+	/// Variables have the form `$NAME`, `${NAME}`, `${NAME:default}` or `${NAME:=default}`.
+	/// The last form assigns the default value to the variable when expanded with [`Self::expand_mut()`].
 	/// A variable name can only consist of ASCII letters, digits and underscores.
 	/// They are allowed to start with numbers.
 	///
-	/// You can escape dollar signs, backslashes, colons and braces with a backslash.
+	/// You can escape dollar signs, backslashes, colons, equals signs and braces with a backslash.
 	#[inline]
 	pub fn from_string(source: String) -> Result<Self, ParseError> {
 		let source = Pin::new(source);
-		let template = Template::from_str(&*source)?;
+		let template = Template::from_str(&source)?;
+
+		// SAFETY: The str slice given to `template` must remain valid.
+		// Since `String` keeps data on the heap, it remains valid when the `source` is moved.
+		// We MUST ensure we do not modify, drop or overwrite `source`.
+		let template = unsafe { template.transmute_lifetime() };
+		let template = NonAliasing::new(template);
+		Ok(Self { source, template })
+	}
+
+	/// Parse a template from a string, with support for include directives.
+	///
+	/// This works the same as [`Self::from_string()`],
+	/// except that include directives in the form `${@include path}` are resolved using the given resolver.
+	/// The `root_id` is the ID of the template itself, if it has one.
+	/// See the [`include`][crate::include] module for more details.
+	#[inline]
+	pub fn from_string_with_includes<R>(source: String, resolver: &R, root_id: Option<&str>) -> Result<Self, ParseError>
+	where
+		R: IncludeResolver + ?Sized,
+	{
+		let source = Pin::new(source);
+		let template = Template::from_str_with_includes(&source, resolver, root_id)?;
 
 		// SAFETY: The str slice given to `template` must remain valid.
 		// Since `String` keeps data on the heap, it remains valid when the `source` is moved.
@@ -188,6 +307,17 @@ impl TemplateBuf {
 	{
 		self.as_template().expand(variables)
 	}
+
+	/// Expand the template, and assign default values to variables in the form `${NAME:=default}`.
+	///
+	/// See [`Template::expand_mut()`] for details.
+	pub fn expand_mut<M>(&self, variables: &mut M) -> Result<String, ExpandError>
+	where
+		M: VariableMapMut + for<'b> VariableMap<'b> + ?Sized,
+		for<'b> <M as VariableMap<'b>>::Value: AsRef<str>,
+	{
+		self.as_template().expand_mut(variables)
+	}
 }
 
 impl<'a> From<&'a TemplateBuf> for &'a Template<'a> {
@@ -217,7 +347,7 @@ impl From<Template<'_>> for TemplateBuf {
 		let source: Pin<String> = Pin::new(other.source.into());
 
 		let template = Template {
-			source: &*source,
+			source: &source,
 			raw: other.raw,
 		};
 
@@ -266,15 +396,68 @@ impl<'a> ByteTemplate<'a> {
 	/// A variable name can only consist of ASCII letters, digits and underscores.
 	/// They are allowed to start with numbers.
 	///
-	/// You can escape dollar signs, backslashes, colons and braces with a backslash.
+	/// You can escape dollar signs, backslashes, colons, equals signs and braces with a backslash.
 	#[inline]
 	pub fn from_slice(source: &'a [u8]) -> Result<Self, ParseError> {
 		Ok(Self {
 			source,
-			raw: raw::Template::parse(source, 0)?,
+			raw: raw::Template::parse(source, 0, &mut raw::ParseContext::new())?,
+		})
+	}
+
+	/// Parse a template from a byte slice, with support for include directives.
+	///
+	/// This works the same as [`Self::from_slice()`],
+	/// except that include directives in the form `${@include path}` are resolved using the given resolver.
+	/// The `root_id` is the ID of the template itself, if it has one.
+	/// See the [`include`][crate::include] module for more details.
+	#[inline]
+	pub fn from_slice_with_includes<R>(
+		source: &'a [u8],
+		resolver: &R,
+		root_id: Option<&str>,
+	) -> Result<Self, ParseError>
+	where
+		R: IncludeResolver + ?Sized,
+	{
+		Ok(Self {
+			source,
+			raw: raw::Template::parse(source, 0, &mut raw::ParseContext::with_includes(&resolver, root_id))?,
 		})
 	}
 
+	/// Parse a template from a byte slice, recovering from syntax errors.
+	///
+	/// Instead of stopping at the first error, this reports all syntax errors in the source,
+	/// together with a best-effort template.
+	/// In the best-effort template, invalid escape sequences and dollar signs that do not start a variable are kept as literal text,
+	/// and variable placeholders without a closing brace extend to the end of the source.
+	///
+	/// If no errors are reported, the template is identical to the one returned by [`Self::from_slice()`].
+	#[inline]
+	pub fn from_slice_recovering(source: &'a [u8]) -> (Self, Vec<ParseError>) {
+		let (raw, errors) = raw::Template::parse_recovering(source, 0, raw::ParseContext::new());
+		(Self { source, raw }, errors)
+	}
+
+	/// Parse a template from a byte slice with support for include directives, recovering from syntax errors.
+	///
+	/// This combines [`Self::from_slice_with_includes()`] and [`Self::from_slice_recovering()`].
+	/// Errors in included templates are reported as [`ParseError::Include`] errors.
+	#[inline]
+	pub fn from_slice_with_includes_recovering<R>(
+		source: &'a [u8],
+		resolver: &R,
+		root_id: Option<&str>,
+	) -> (Self, Vec<ParseError>)
+	where
+		R: IncludeResolver + ?Sized,
+	{
+		let context = raw::ParseContext::with_includes(&resolver, root_id);
+		let (raw, errors) = raw::Template::parse_recovering(source, 0, context);
+		(Self { source, raw }, errors)
+	}
+
 	/// Get the original source slice.
 	#[inline]
 	pub fn source(&self) -> &[u8] {
@@ -297,6 +480,32 @@ impl<'a> ByteTemplate<'a> {
 		Ok(output)
 	}
 
+	/// Expand the template, and assign default values to variables in the form `${NAME:=default}`.
+	///
+	/// This works like [`Self::expand()`], but if a variable with the form `${NAME:=default}` is missing from the map,
+	/// the expanded default value is also stored in the map with [`VariableMapMut::set()`].
+	/// See [`Template::expand_mut()`] for details.
+	///
+	/// # Example
+	/// ```
+	/// # use std::collections::BTreeMap;
+	/// # use subst::ByteTemplate;
+	/// let mut variables: BTreeMap<String, Vec<u8>> = BTreeMap::new();
+	///
+	/// let template = ByteTemplate::from_slice(b"${NAME:=world}, hello $NAME!").unwrap();
+	/// assert_eq!(template.expand_mut(&mut variables).unwrap(), b"world, hello world!");
+	/// assert_eq!(variables["NAME"], b"world");
+	/// ```
+	pub fn expand_mut<M>(&self, variables: &mut M) -> Result<Vec<u8>, ExpandError>
+	where
+		M: VariableMapMut<Vec<u8>> + for<'b> VariableMap<'b> + ?Sized,
+		for<'b> <M as VariableMap<'b>>::Value: AsRef<[u8]>,
+	{
+		let mut output = Vec::with_capacity(self.source.len() + self.source.len() / 10);
+		self.raw.expand_mut(&mut output, self.source, variables)?;
+		Ok(output)
+	}
+
 	/// Transmute the lifetime of the source data.
 	///
 	/// # Safety:
@@ -335,7 +544,7 @@ impl Clone for ByteTemplateBuf {
 
 		let template = ByteTemplate {
 			raw,
-			source: &*source,
+			source: &source,
 		};
 
 		// SAFETY: The slice given to `template` must remain valid.
@@ -370,11 +579,34 @@ impl ByteTemplateBuf {
 	/// A variable name can only consist of ASCII letters, digits and underscores.
 	/// They are allowed to start with numbers.
 	///
-	/// You can escape dollar signs, backslashes, colons and braces with a backslash.
+	/// You can escape dollar signs, backslashes, colons, equals signs and braces with a backslash.
 	#[inline]
 	pub fn from_vec(source: Vec<u8>) -> Result<Self, ParseError> {
 		let source = Pin::new(source);
-		let template = ByteTemplate::from_slice(&*source)?;
+		let template = ByteTemplate::from_slice(&source)?;
+
+		// SAFETY: The slice given to `template` must remain valid.
+		// Since `Vec` keeps data on the heap, it remains valid when the `source` is moved.
+		// We MUST ensure we do not modify, drop or overwrite `source`.
+		let template = unsafe { template.transmute_lifetime() };
+		let template = NonAliasing::new(template);
+
+		Ok(Self { source, template })
+	}
+
+	/// Parse a template from a vector of bytes, with support for include directives.
+	///
+	/// This works the same as [`Self::from_vec()`],
+	/// except that include directives in the form `${@include path}` are resolved using the given resolver.
+	/// The `root_id` is the ID of the template itself, if it has one.
+	/// See the [`include`][crate::include] module for more details.
+	#[inline]
+	pub fn from_vec_with_includes<R>(source: Vec<u8>, resolver: &R, root_id: Option<&str>) -> Result<Self, ParseError>
+	where
+		R: IncludeResolver + ?Sized,
+	{
+		let source = Pin::new(source);
+		let template = ByteTemplate::from_slice_with_includes(&source, resolver, root_id)?;
 
 		// SAFETY: The slice given to `template` must remain valid.
 		// Since `Vec` keeps data on the heap, it remains valid when the `source` is moved.
@@ -413,6 +645,17 @@ impl ByteTemplateBuf {
 	{
 		self.as_template().expand(variables)
 	}
+
+	/// Expand the template, and assign default values to variables in the form `${NAME:=default}`.
+	///
+	/// See [`ByteTemplate::expand_mut()`] for details.
+	pub fn expand_mut<M>(&self, variables: &mut M) -> Result<Vec<u8>, ExpandError>
+	where
+		M: VariableMapMut<Vec<u8>> + for<'b> VariableMap<'b> + ?Sized,
+		for<'b> <M as VariableMap<'b>>::Value: AsRef<[u8]>,
+	{
+		self.as_template().expand_mut(variables)
+	}
 }
 
 impl<'a> From<&'a ByteTemplateBuf> for &'a ByteTemplate<'a> {
@@ -443,7 +686,7 @@ impl From<ByteTemplate<'_>> for ByteTemplateBuf {
 		let source = Pin::new(source);
 
 		let template = ByteTemplate {
-			source: &*source,
+			source: &source,
 			raw: other.raw,
 		};
 
@@ -535,4 +778,82 @@ mod tests {
 		let_assert!(Ok(buf1) = ByteTemplateBuf::from_vec(source.into()));
 		check_template(buf1);
 	}
+
+	#[test]
+	fn test_from_str_recovering() {
+		use crate::error::ParseError;
+
+		let map: BTreeMap<&str, &str> = [("name", "world"), ("greeting", "Hello")].into_iter().collect();
+
+		let (template, errors) = Template::from_str_recovering("$greeting ${name}!");
+		assert!(errors.is_empty());
+		check!(let Ok("Hello world!") = template.expand(&map).as_deref());
+
+		let source = r"\q $greeting $! ${name) ${name:\x} ${ ${name:${greeting}";
+		let (template, errors) = Template::from_str_recovering(source);
+		let_assert!(
+			[
+				ParseError::InvalidEscapeSequence(e1),
+				ParseError::MissingVariableName(e2),
+				ParseError::UnexpectedCharacter(e3),
+				ParseError::InvalidEscapeSequence(e4),
+				ParseError::MissingVariableName(e5),
+				ParseError::MissingClosingBrace(e6),
+			] = errors.as_slice()
+		);
+		check!(e1.position == 0);
+		check!(e2.position == 13);
+		check!(e3.position == 22);
+		check!(e4.position == 31);
+		check!(e5.position == 35);
+		check!(e6.position == 39);
+		check!(let Ok(r"\q Hello $! world world ${ world") = template.expand(&map).as_deref());
+	}
+
+	#[test]
+	fn test_from_slice_recovering() {
+		use crate::error::ParseError;
+
+		let map: BTreeMap<&str, &str> = [("name", "world")].into_iter().collect();
+		let (template, errors) = ByteTemplate::from_slice_recovering(b"Hello ${name:\\\xFF}, $");
+		let_assert!(
+			[
+				ParseError::InvalidEscapeSequence(e1),
+				ParseError::MissingVariableName(e2)
+			] = errors.as_slice()
+		);
+		check!(e1.position == 13);
+		check!(e2.position == 18);
+		check!(let Ok(b"Hello world, $") = template.expand(&map).as_deref());
+	}
+
+	#[test]
+	fn test_from_str_with_includes_recovering() {
+		use crate::error::{IncludeErrorKind, ParseError};
+		use crate::include::MemoryResolver;
+
+		let resolver: MemoryResolver = [("a", "${x ${y"), ("b", "${@include a}")].into_iter().collect();
+		let (_template, errors) =
+			Template::from_str_with_includes_recovering("${@include b} ${@include c} $", &resolver, None);
+		let_assert!(
+			[
+				ParseError::Include(e1),
+				ParseError::Include(e2),
+				ParseError::Include(e3),
+				ParseError::MissingVariableName(e4)
+			] = errors.as_slice()
+		);
+		check!(e1.chain == ["b", "a"]);
+		check!(e1.position == 0);
+		let_assert!(IncludeErrorKind::Parse(inner) = &e1.kind);
+		let_assert!(ParseError::UnexpectedCharacter(inner) = &**inner);
+		check!(inner.position == 3);
+		check!(e2.chain == ["b", "a"]);
+		let_assert!(IncludeErrorKind::Parse(inner) = &e2.kind);
+		let_assert!(ParseError::MissingClosingBrace(inner) = &**inner);
+		check!(inner.position == 5);
+		check!(e3.chain == ["c"]);
+		check!(e3.position == 14);
+		check!(e4.position == 28);
+	}
 }
@@ -1,7 +1,44 @@
-use super::{Part, Template, Variable};
+use super::{Include, Part, Template, Variable};
 use crate::error::{self, ExpandError};
+use crate::map::VariableMapMut;
 use crate::VariableMap;
 
+/// A type for values that can be assigned to variables while expanding a template.
+///
+/// The type parameter is the type of the values retrieved from the variable map.
+pub trait AssignValue<V>: Sized {
+	/// Get the bytes of a value retrieved from the variable map.
+	fn as_bytes(value: &V) -> &[u8];
+
+	/// Create a value from the expanded default value of a variable.
+	fn from_expanded(expanded: &[u8]) -> Self;
+}
+
+impl<V: AsRef<str>> AssignValue<V> for String {
+	#[inline]
+	fn as_bytes(value: &V) -> &[u8] {
+		value.as_ref().as_bytes()
+	}
+
+	#[inline]
+	fn from_expanded(expanded: &[u8]) -> Self {
+		// The source and all values are valid UTF-8 and the default value consists of whole parts of them, so it is valid UTF-8 too.
+		std::str::from_utf8(expanded).unwrap().to_owned()
+	}
+}
+
+impl<V: AsRef<[u8]>> AssignValue<V> for Vec<u8> {
+	#[inline]
+	fn as_bytes(value: &V) -> &[u8] {
+		value.as_ref()
+	}
+
+	#[inline]
+	fn from_expanded(expanded: &[u8]) -> Self {
+		expanded.to_vec()
+	}
+}
+
 impl Template {
 	/// Expand the template into the output vector.
 	pub fn expand<'a, M, F>(
@@ -21,6 +58,26 @@ impl Template {
 				Part::Literal(x) => output.extend_from_slice(&source[x.range.clone()]),
 				Part::EscapedByte(x) => output.push(x.value),
 				Part::Variable(x) => x.expand(output, source, variables, to_bytes)?,
+				Part::Include(x) => x.expand(output, variables, to_bytes)?,
+			}
+		}
+		Ok(())
+	}
+
+	/// Expand the template into the output vector, assigning default values to variables where requested.
+	///
+	/// For [`String`] values, the source and all values in the map must be valid UTF-8.
+	pub fn expand_mut<M, T>(&self, output: &mut Vec<u8>, source: &[u8], variables: &mut M) -> Result<(), ExpandError>
+	where
+		M: VariableMapMut<T> + for<'a> VariableMap<'a> + ?Sized,
+		T: for<'a> AssignValue<<M as VariableMap<'a>>::Value>,
+	{
+		for part in &self.parts {
+			match part {
+				Part::Literal(x) => output.extend_from_slice(&source[x.range.clone()]),
+				Part::EscapedByte(x) => output.push(x.value),
+				Part::Variable(x) => x.expand_mut(output, source, variables)?,
+				Part::Include(x) => x.expand_mut(output, variables)?,
 			}
 		}
 		Ok(())
@@ -28,6 +85,15 @@ impl Template {
 }
 
 impl Variable {
+	/// Get the name to look up the variable in the variable map.
+	fn name<'s>(&'s self, source: &'s [u8]) -> &'s str {
+		match &self.unescaped_name {
+			Some(name) => name,
+			// Names and scheme keys were already checked while parsing, so they are guaranteed to be valid UTF-8.
+			None => std::str::from_utf8(&source[self.name.clone()]).unwrap(),
+		}
+	}
+
 	/// Expand the variable into the output vector.
 	fn expand<'a, M, F>(
 		&self,
@@ -40,22 +106,134 @@ impl Variable {
 		M: VariableMap<'a> + ?Sized,
 		F: Fn(&M::Value) -> &[u8],
 	{
-		// Names were already checked to match a restricted set of valid characters, so they are guaranteed to be valid UTF-8.
-		let name = std::str::from_utf8(&source[self.name.clone()]).unwrap();
+		let name = self.name(source);
+
+		// If the map refuses to give the variable, raise an error without trying the default value.
+		let value = variables.lookup(name).map_err(|e| self.lookup_error(name, e))?;
 
 		// If the variable appears in the map, use the value from the map.
-		if let Some(value) = variables.get(name) {
+		if let Some(value) = value {
 			output.extend_from_slice(to_bytes(&value));
 			Ok(())
 		// Otherwise, use the default value, if given in the template.
 		} else if let Some(default) = &self.default {
-			default.expand(output, source, variables, to_bytes)
+			default
+				.expand(output, source, variables, to_bytes)
+				.map_err(|e| self.default_error(name, e))
 		// Else, raise an error.
 		} else {
-			Err(ExpandError::NoSuchVariable(error::NoSuchVariable {
+			Err(self.missing_error(name))
+		}
+	}
+
+	/// Expand the variable into the output vector, and assign the default value to the variable if requested.
+	fn expand_mut<M, T>(&self, output: &mut Vec<u8>, source: &[u8], variables: &mut M) -> Result<(), ExpandError>
+	where
+		M: VariableMapMut<T> + for<'a> VariableMap<'a> + ?Sized,
+		T: for<'a> AssignValue<<M as VariableMap<'a>>::Value>,
+	{
+		let name = self.name(source);
+
+		// Copy the value from the map before the map is borrowed mutably to assign a default value.
+		let found = match variables.lookup(name).map_err(|e| self.lookup_error(name, e))? {
+			Some(value) => {
+				output.extend_from_slice(T::as_bytes(&value));
+				true
+			},
+			None => false,
+		};
+		if found {
+			return Ok(());
+		}
+
+		let Some(default) = &self.default else {
+			return Err(self.missing_error(name));
+		};
+		let start = output.len();
+		default
+			.expand_mut(output, source, variables)
+			.map_err(|e| self.default_error(name, e))?;
+		if self.assign {
+			variables.set(name, T::from_expanded(&output[start..]));
+		}
+		Ok(())
+	}
+
+	/// Convert an error from the variable map to an expansion error.
+	fn lookup_error(&self, name: &str, error: error::LookupError) -> ExpandError {
+		match error {
+			error::LookupError::NotPermitted => ExpandError::NotPermitted(error::VariableNotPermitted {
+				position: self.name.start,
+				name: name.to_owned(),
+			}),
+			error::LookupError::Cycle(cycle) => ExpandError::Cycle(error::VariableCycle {
 				position: self.name.start,
 				name: name.to_owned(),
-			}))
+				cycle,
+			}),
+		}
+	}
+
+	/// Add context to an error from expanding the default value.
+	fn default_error(&self, name: &str, error: ExpandError) -> ExpandError {
+		match error {
+			// Record that this variable was tried first, so the error can report the whole chain.
+			ExpandError::NoSuchVariable(mut e) => {
+				e.tried.insert(0, error::TriedVariable {
+					position: self.name.start,
+					name: name.to_owned(),
+				});
+				ExpandError::NoSuchVariable(e)
+			},
+			e => e,
 		}
 	}
+
+	/// Create the error for a missing variable without a default value.
+	fn missing_error(&self, name: &str) -> ExpandError {
+		ExpandError::NoSuchVariable(error::NoSuchVariable {
+			position: self.name.start,
+			name: name.to_owned(),
+			tried: Vec::new(),
+			suggestions: Vec::new(),
+		})
+	}
+}
+
+impl Include {
+	/// Expand the included template into the output vector.
+	fn expand<'a, M, F>(&self, output: &mut Vec<u8>, variables: &'a M, to_bytes: &F) -> Result<(), ExpandError>
+	where
+		M: VariableMap<'a> + ?Sized,
+		F: Fn(&M::Value) -> &[u8],
+	{
+		self.template
+			.expand(output, self.source.as_bytes(), variables, to_bytes)
+			.map_err(|e| self.include_error(e))
+	}
+
+	/// Expand the included template into the output vector, assigning default values to variables where requested.
+	fn expand_mut<M, T>(&self, output: &mut Vec<u8>, variables: &mut M) -> Result<(), ExpandError>
+	where
+		M: VariableMapMut<T> + for<'a> VariableMap<'a> + ?Sized,
+		T: for<'a> AssignValue<<M as VariableMap<'a>>::Value>,
+	{
+		self.template
+			.expand_mut(output, self.source.as_bytes(), variables)
+			.map_err(|e| self.include_error(e))
+	}
+
+	/// Add the include directive to an error from expanding the included template.
+	fn include_error(&self, error: ExpandError) -> ExpandError {
+		let error = match error {
+			ExpandError::Include(e) => e.included_from(&self.id, self.range.start, self.range.len()),
+			e => error::IncludeError {
+				position: self.range.start,
+				len: self.range.len(),
+				chain: vec![self.id.to_string()],
+				kind: error::IncludeErrorKind::Expand(Box::new(e)),
+			},
+		};
+		ExpandError::Include(error)
+	}
 }
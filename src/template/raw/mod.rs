@@ -1,5 +1,6 @@
 mod expand;
 mod parse;
+pub use parse::ParseContext;
 
 /// Raw template that doesn't know track the original source.
 ///
@@ -10,6 +11,31 @@ pub struct Template {
 	parts: Vec<Part>,
 }
 
+impl Template {
+	/// Find the variables with a default value that contains the given position.
+	///
+	/// Returns the range from the dollar sign up to and including the colon (or `:=`) for each variable,
+	/// from the outermost to the innermost.
+	#[cfg_attr(not(feature = "codespan-reporting"), allow(dead_code))]
+	pub fn enclosing_defaults(&self, position: usize) -> Vec<std::ops::Range<usize>> {
+		let mut enclosing = Vec::new();
+		let mut template = self;
+		'outer: loop {
+			for part in &template.parts {
+				let Part::Variable(variable) = part else { continue };
+				let Some(default) = &variable.default else { continue };
+				let default_start = variable.name.end + 1 + usize::from(variable.assign);
+				if (default_start..variable.range.end).contains(&position) {
+					enclosing.push(variable.range.start..default_start);
+					template = default;
+					continue 'outer;
+				}
+			}
+			return enclosing;
+		}
+	}
+}
+
 /// One piece of a parsed template.
 #[derive(Clone)]
 pub enum Part {
@@ -21,6 +47,9 @@ pub enum Part {
 
 	/// A variable to be substituted at expansion time.
 	Variable(Variable),
+
+	/// An included template.
+	Include(Include),
 }
 
 /// A literal string to be used verbatim from the original source.
@@ -46,6 +75,11 @@ pub struct EscapedByte {
 /// A variable to be substituted at expansion time.
 #[derive(Clone)]
 pub struct Variable {
+	/// The range of the whole variable in the source, from the dollar sign up to and including the closing brace.
+	///
+	/// Used to find the variables that enclose a position in a default value.
+	range: std::ops::Range<usize>,
+
 	/// The range in the source defining the name of the variable.
 	///
 	/// Used for look-up in the variable map at expansion time.
@@ -55,4 +89,34 @@ pub struct Variable {
 	///
 	/// Will be used if the variable does not appear in the variable map at expansion time.
 	default: Option<Template>,
+
+	/// Assign the default value to the variable if it is used.
+	///
+	/// Set for variables in the form `${name:=default}`.
+	/// Only has an effect when expanding with a mutable variable map.
+	assign: bool,
+
+	/// The name of the variable with escape sequences resolved, if the name contains any.
+	///
+	/// Only the keys of scheme-prefixed variables can contain escape sequences.
+	/// If this is set, it is used for look-up instead of the name in the source.
+	unescaped_name: Option<Box<str>>,
+}
+
+/// An included template.
+#[derive(Clone)]
+pub struct Include {
+	/// The range of the include directive in the source of the including template.
+	///
+	/// Used to report errors in the included template at expansion time.
+	range: std::ops::Range<usize>,
+
+	/// The ID of the included template, as given by the resolver.
+	id: std::sync::Arc<str>,
+
+	/// The source of the included template.
+	source: std::sync::Arc<str>,
+
+	/// The parsed included template.
+	template: Template,
 }
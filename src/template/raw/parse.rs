@@ -1,12 +1,82 @@
-use super::{EscapedByte, Literal, Part, Template, Variable};
+use super::{EscapedByte, Include, Literal, Part, Template, Variable};
 use crate::error::{self, ParseError};
+use crate::IncludeResolver;
+
+/// Settings and state used while parsing a template.
+pub struct ParseContext<'r> {
+	/// The resolver used to load included templates, if includes are enabled.
+	resolver: Option<&'r dyn IncludeResolver>,
+
+	/// The IDs of the included templates that are currently being parsed.
+	///
+	/// Used to detect include cycles.
+	include_stack: Vec<String>,
+
+	/// The errors encountered so far, if the parser should recover from errors.
+	///
+	/// If this is `None`, parsing stops at the first error.
+	errors: Option<Vec<ParseError>>,
+}
+
+impl<'r> ParseContext<'r> {
+	/// Create a parse context without support for include directives.
+	pub fn new() -> Self {
+		Self {
+			resolver: None,
+			include_stack: Vec::new(),
+			errors: None,
+		}
+	}
+
+	/// Create a parse context that loads included templates with the given resolver.
+	///
+	/// If the template being parsed has an ID, it is put on the include stack,
+	/// so that including the template from one of its included templates is reported as a cycle.
+	pub fn with_includes(resolver: &'r dyn IncludeResolver, root_id: Option<&str>) -> Self {
+		Self {
+			resolver: Some(resolver),
+			include_stack: root_id.map(String::from).into_iter().collect(),
+			..Self::new()
+		}
+	}
+
+	/// Make the parser recover from errors instead of stopping at the first error.
+	///
+	/// The errors can be retrieved with [`Self::into_errors()`] after parsing.
+	fn recovering(self) -> Self {
+		Self {
+			errors: Some(Vec::new()),
+			..self
+		}
+	}
+
+	/// Get the errors that the parser recovered from.
+	fn into_errors(self) -> Vec<ParseError> {
+		self.errors.unwrap_or_default()
+	}
+
+	/// Report a parse error.
+	///
+	/// If the parser recovers from errors, the error is recorded and `Ok(())` is returned,
+	/// after which the caller must continue parsing with a best-effort result.
+	/// Otherwise, the error is returned.
+	fn recover(&mut self, error: impl Into<ParseError>) -> Result<(), ParseError> {
+		match &mut self.errors {
+			Some(errors) => {
+				errors.push(error.into());
+				Ok(())
+			},
+			None => Err(error.into()),
+		}
+	}
+}
 
 impl Template {
 	/// Parse the template from a source slice starting at the given position.
 	///
 	/// You must pass the entire source slice and an offset,
 	/// so that source positions in errors are correct.
-	pub fn parse(source: &[u8], start: usize) -> Result<Self, ParseError> {
+	pub fn parse(source: &[u8], start: usize, context: &mut ParseContext) -> Result<Self, ParseError> {
 		let mut parts = Vec::with_capacity(1);
 		let mut finger = start;
 		while finger < source.len() {
@@ -28,20 +98,67 @@ impl Template {
 
 			// We found an escape sequence.
 			if source[next] == b'\\' {
-				let value = unescape_one(source, next)?;
-				parts.push(Part::EscapedByte(EscapedByte { value }));
-				finger = next + 2;
-
-			// We found a variable substitution.
-			} else {
-				let (variable, end) = Variable::parse(source, next)?;
-				finger = end;
-				parts.push(Part::Variable(variable));
+				match unescape_one(source, next) {
+					Ok(value) => {
+						parts.push(Part::EscapedByte(EscapedByte { value }));
+						finger = next + 2;
+					},
+					// When recovering, keep the backslash as literal text.
+					Err(e) => {
+						context.recover(e)?;
+						parts.push(Part::Literal(Literal { range: next..next + 1 }));
+						finger = next + 1;
+					},
+				}
+				continue;
+			}
+
+			// We found an include directive or a variable substitution.
+			let parsed = match Include::parse(source, next, context) {
+				Ok(Some((include, end))) => Ok((Part::Include(include), end)),
+				Ok(None) => {
+					Variable::parse(source, next, context).map(|(variable, end)| (Part::Variable(variable), end))
+				},
+				Err(e) => Err(e),
+			};
+			match parsed {
+				Ok((part, end)) => {
+					parts.push(part);
+					finger = end;
+				},
+				// When recovering, skip invalid include directives and keep other dollar signs as literal text.
+				Err(e) => {
+					finger = match &e {
+						ParseError::Include(e) => e.position + e.len,
+						_ => {
+							parts.push(Part::Literal(Literal { range: next..next + 1 }));
+							next + 1
+						},
+					};
+					context.recover(e)?;
+				},
 			}
 		}
 
 		Ok(Self { parts })
 	}
+
+	/// Parse the template from a source slice, recovering from errors.
+	///
+	/// Returns a best-effort template and all errors encountered while parsing.
+	pub fn parse_recovering(source: &[u8], start: usize, context: ParseContext) -> (Self, Vec<ParseError>) {
+		let mut context = context.recovering();
+		// All errors are recorded in the context, so this should not fail.
+		// But if it does, report the error and return an empty template.
+		let template = match Self::parse(source, start, &mut context) {
+			Ok(template) => template,
+			Err(e) => {
+				context.recover(e).ok();
+				Self { parts: Vec::new() }
+			},
+		};
+		(template, context.into_errors())
+	}
 }
 
 impl Variable {
@@ -50,7 +167,7 @@ impl Variable {
 	/// The finger must be the position of the dollar sign in the source.
 	///
 	/// Returns the parsed variable and the index of the byte after the variable.
-	fn parse(source: &[u8], finger: usize) -> Result<(Self, usize), ParseError> {
+	fn parse(source: &[u8], finger: usize, context: &mut ParseContext) -> Result<(Self, usize), ParseError> {
 		if finger + 1 >= source.len() {
 			return Err(error::MissingVariableName {
 				position: finger,
@@ -59,7 +176,7 @@ impl Variable {
 			.into());
 		}
 		if source[finger + 1] == b'{' {
-			Self::parse_braced(source, finger)
+			Self::parse_braced(source, finger, context)
 		} else {
 			let name_end = match source[finger + 1..]
 				.iter()
@@ -76,19 +193,22 @@ impl Variable {
 				None => source.len(),
 			};
 			let variable = Variable {
+				range: finger..name_end,
 				name: finger + 1..name_end,
 				default: None,
+				assign: false,
+				unescaped_name: None,
 			};
 			Ok((variable, name_end))
 		}
 	}
 
-	/// Parse a braced variable in the form of "${name[:default]} from source at the given position.
+	/// Parse a braced variable in the form of "${name[:default]}" or "${@scheme:key[:default]}" from source at the given position.
 	///
 	/// The finger must be the position of the dollar sign in the source.
 	///
 	/// Returns the parsed variable and the index of the byte after the variable.
-	fn parse_braced(source: &[u8], finger: usize) -> Result<(Self, usize), ParseError> {
+	fn parse_braced(source: &[u8], finger: usize, context: &mut ParseContext) -> Result<(Self, usize), ParseError> {
 		let name_start = finger + 2;
 		if name_start >= source.len() {
 			return Err(error::MissingVariableName {
@@ -98,64 +218,263 @@ impl Variable {
 			.into());
 		}
 
-		// Get the first sequence of alphanumeric characters and underscores for the variable name.
-		let name_end = match source[name_start..]
-			.iter()
-			.position(|&c| !c.is_ascii_alphanumeric() && c != b'_')
-		{
-			Some(0) => {
-				return Err(error::MissingVariableName {
-					position: finger,
-					len: 2,
-				}
-				.into());
-			},
-			Some(x) => name_start + x,
-			None => source.len(),
+		// A name starting with an `@` is a scheme followed by a key, and the name used for look-up is "scheme:key".
+		// Otherwise, get the first sequence of alphanumeric characters and underscores for the variable name.
+		let (name_start, name_end, unescaped_name, expected) = if source[name_start] == b'@' {
+			let (start, end, unescaped_name) = parse_scheme_key(source, finger)?;
+			(start, end, unescaped_name, error::ExpectedCharacter::AFTER_SCHEME_KEY)
+		} else {
+			let name_end = match source[name_start..]
+				.iter()
+				.position(|&c| !c.is_ascii_alphanumeric() && c != b'_')
+			{
+				Some(0) => {
+					return Err(error::MissingVariableName {
+						position: finger,
+						len: 2,
+					}
+					.into());
+				},
+				Some(x) => name_start + x,
+				None => source.len(),
+			};
+			(name_start, name_end, None, error::ExpectedCharacter::AFTER_NAME)
 		};
 
 		// If the name extends to the end, we're missing a closing brace.
+		// When recovering, pretend the closing brace is at the end.
 		if name_end == source.len() {
-			return Err(error::MissingClosingBrace { position: finger + 1 }.into());
+			context.recover(error::MissingClosingBrace { position: finger + 1 })?;
+			let variable = Variable {
+				range: finger..name_end,
+				name: name_start..name_end,
+				default: None,
+				assign: false,
+				unescaped_name,
+			};
+			return Ok((variable, name_end));
 		}
 
 		// If there is a closing brace after the name, there is no default value and we're done.
 		if source[name_end] == b'}' {
 			let variable = Variable {
+				range: finger..name_end + 1,
 				name: name_start..name_end,
 				default: None,
+				assign: false,
+				unescaped_name,
 			};
 			return Ok((variable, name_end + 1));
 
 		// If there is something other than a closing brace or colon after the name, it's an error.
+		// When recovering, skip to the closing brace if it comes before the next variable or line break,
+		// otherwise skip only the unexpected character.
 		} else if source[name_end] != b':' {
-			return Err(error::UnexpectedCharacter {
+			let character = get_maybe_char_at(source, name_end);
+			context.recover(error::UnexpectedCharacter {
 				position: name_end,
-				character: get_maybe_char_at(source, name_end),
-				expected: error::ExpectedCharacter {
-					message: "a closing brace ('}') or colon (':')",
-				},
-			}
-			.into());
+				character,
+				expected,
+			})?;
+			let end = match source[name_end..].iter().position(|c| b"}${\n".contains(c)) {
+				Some(x) if source[name_end + x] == b'}' => name_end + x + 1,
+				_ => name_end + character.source_len(),
+			};
+			let variable = Variable {
+				range: finger..end,
+				name: name_start..name_end,
+				default: None,
+				assign: false,
+				unescaped_name,
+			};
+			return Ok((variable, end));
 		}
 
 		// If there is no matching un-escaped closing brace, it's missing.
-		let end = finger
-			+ find_closing_brace(&source[finger..]).ok_or(error::MissingClosingBrace { position: finger + 1 })?;
+		// When recovering, pretend the closing brace is at the end.
+		let end = match find_closing_brace(&source[finger..]) {
+			Some(x) => finger + x,
+			None => {
+				context.recover(error::MissingClosingBrace { position: finger + 1 })?;
+				source.len()
+			},
+		};
 
+		// A colon followed by an equals sign assigns the default value to the variable when expanding with a mutable map.
+		let assign = source.get(name_end + 1) == Some(&b'=');
+		let default_start = name_end + 1 + usize::from(assign);
+		let variable_end = (end + 1).min(source.len());
 		let variable = Variable {
+			range: finger..variable_end,
 			name: name_start..name_end,
-			default: Some(Template::parse(&source[..end], name_end + 1)?),
+			default: Some(Template::parse(&source[..end], default_start, context)?),
+			assign,
+			unescaped_name,
 		};
-		Ok((variable, end + 1))
+		Ok((variable, variable_end))
 	}
 }
 
+impl Include {
+	/// Parse an include directive in the form of "${@include path}" from source at the given position.
+	///
+	/// The finger must be the position of the dollar sign in the source.
+	///
+	/// Returns `None` if there is no include directive at the given position.
+	/// Otherwise, returns the included template and the index of the byte after the directive.
+	fn parse(source: &[u8], finger: usize, context: &mut ParseContext) -> Result<Option<(Self, usize)>, ParseError> {
+		const DIRECTIVE: &[u8] = b"${@include";
+
+		// The directive must be followed by whitespace or the closing brace.
+		let path_start = finger + DIRECTIVE.len();
+		if !source[finger..].starts_with(DIRECTIVE) {
+			return Ok(None);
+		}
+		match source.get(path_start) {
+			Some(b'}') => (),
+			Some(c) if c.is_ascii_whitespace() => (),
+			_ => return Ok(None),
+		}
+
+		let path_end = match memchr::memchr(b'}', &source[path_start..]) {
+			Some(x) => path_start + x,
+			None => return Err(error::MissingClosingBrace { position: finger + 1 }.into()),
+		};
+		let range = finger..path_end + 1;
+		let include_error = |chain: Vec<String>, kind| error::IncludeError {
+			position: range.start,
+			len: range.len(),
+			chain,
+			kind,
+		};
+
+		let resolver = context
+			.resolver
+			.ok_or_else(|| include_error(Vec::new(), error::IncludeErrorKind::NotSupported))?;
+
+		let path = source[path_start..path_end].trim_ascii();
+		if path.is_empty() {
+			return Err(include_error(Vec::new(), error::IncludeErrorKind::MissingPath).into());
+		}
+		let path = std::str::from_utf8(path).map_err(|_| {
+			let path = String::from_utf8_lossy(path).into_owned();
+			include_error(
+				vec![path],
+				error::IncludeErrorKind::Resolve("path is not valid UTF-8".into()),
+			)
+		})?;
+
+		let parent = context.include_stack.last().map(String::as_str);
+		let included = resolver
+			.resolve(path, parent)
+			.map_err(|e| include_error(vec![path.into()], error::IncludeErrorKind::Resolve(e.to_string())))?;
+		if context.include_stack.contains(&included.id) {
+			return Err(include_error(vec![included.id], error::IncludeErrorKind::Cycle).into());
+		}
+
+		// Errors in the included template are relative to the included source,
+		// so collect them separately when recovering from errors.
+		context.include_stack.push(included.id);
+		let outer_errors = context.errors.as_mut().map(std::mem::take);
+		let template = Template::parse(included.source.as_bytes(), 0, context);
+		let inner_errors = std::mem::replace(&mut context.errors, outer_errors);
+		let id = context.include_stack.pop().unwrap();
+
+		let wrap_error = |e| match e {
+			ParseError::Include(e) => e.included_from(&id, range.start, range.len()),
+			e => include_error(vec![id.clone()], error::IncludeErrorKind::Parse(Box::new(e))),
+		};
+		if let (Some(errors), Some(inner_errors)) = (&mut context.errors, inner_errors) {
+			errors.extend(inner_errors.into_iter().map(|e| wrap_error(e).into()));
+		}
+		let template = template.map_err(wrap_error)?;
+
+		let include = Include {
+			range: range.clone(),
+			id: id.into(),
+			source: included.source.into(),
+			template,
+		};
+		Ok(Some((include, range.end)))
+	}
+}
+
+/// Parse the scheme and key of a scheme-prefixed variable in the form "${@scheme:key" from source at the given position.
+///
+/// The finger must be the position of the dollar sign in the source.
+///
+/// Returns the range of "scheme:key" in the source, without the `@`.
+/// If the key contains escape sequences, this also returns the name "scheme:key" with the escape sequences resolved.
+fn parse_scheme_key(source: &[u8], finger: usize) -> Result<(usize, usize, Option<Box<str>>), ParseError> {
+	let scheme_start = finger + 3;
+	let scheme_end = source[scheme_start..]
+		.iter()
+		.position(|&c| !c.is_ascii_alphanumeric() && c != b'_')
+		.map_or(source.len(), |x| scheme_start + x);
+	if scheme_end == scheme_start {
+		return Err(error::MissingVariableName {
+			position: finger,
+			len: 3,
+		}
+		.into());
+	}
+	match source.get(scheme_end) {
+		Some(b':') => (),
+		Some(_) => {
+			return Err(error::UnexpectedCharacter {
+				position: scheme_end,
+				character: get_maybe_char_at(source, scheme_end),
+				expected: error::ExpectedCharacter::AFTER_SCHEME,
+			}
+			.into());
+		},
+		None => return Err(error::MissingClosingBrace { position: finger + 1 }.into()),
+	}
+
+	// The key can contain anything except whitespace, control characters and the special characters of the template syntax.
+	// The special characters can be included with the usual escape sequences, like `\:`.
+	// The key must also be valid UTF-8, so an invalid byte ends the key too.
+	let key_start = scheme_end + 1;
+	let mut key_end = key_start;
+	let mut unescaped: Option<Vec<u8>> = None;
+	loop {
+		let len = source[key_end..]
+			.iter()
+			.position(|&c| b"}:${\\".contains(&c) || c.is_ascii_whitespace() || c.is_ascii_control())
+			.unwrap_or(source.len() - key_end);
+		let len = valid_utf8_prefix(&source[key_end..key_end + len]).len();
+		if let Some(unescaped) = &mut unescaped {
+			unescaped.extend_from_slice(&source[key_end..key_end + len]);
+		}
+		key_end += len;
+
+		if source.get(key_end) != Some(&b'\\') {
+			break;
+		}
+		let value = unescape_one(source, key_end)?;
+		unescaped
+			.get_or_insert_with(|| source[scheme_start..key_end].to_vec())
+			.push(value);
+		key_end += 2;
+	}
+	if key_end == key_start {
+		return Err(error::MissingVariableName {
+			position: finger,
+			len: key_start - finger,
+		}
+		.into());
+	}
+
+	// The unescaped name consists of valid UTF-8 and escaped ASCII characters, so it is valid UTF-8 too.
+	let unescaped = unescaped.map(|name| String::from_utf8(name).unwrap().into_boxed_str());
+	Ok((scheme_start, key_end, unescaped))
+}
+
 /// Unescape a single escape sequence in source at the given position.
 ///
 /// The `position` must point to the backslash character in the source text.
 ///
-/// Only valid escape sequences ('\$' '\{' '\}' and '\:') are accepted.
+/// Only valid escape sequences ('\\' '\$' '\{' '\}' '\:' and '\=') are accepted.
 /// Invalid escape sequences cause an error to be returned.
 fn unescape_one(source: &[u8], position: usize) -> Result<u8, ParseError> {
 	if position == source.len() - 1 {
@@ -171,6 +490,7 @@ fn unescape_one(source: &[u8], position: usize) -> Result<u8, ParseError> {
 		b'{' => Ok(b'{'),
 		b'}' => Ok(b'}'),
 		b':' => Ok(b':'),
+		b'=' => Ok(b'='),
 		_ => Err(error::InvalidEscapeSequence {
 			position,
 			character: Some(get_maybe_char_at(source, position + 1)),
@@ -0,0 +1,55 @@
+//! Helpers for unit tests.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// A temporary directory that is removed when dropped.
+///
+/// The directory is created before anything is written to it,
+/// so it is also removed when a test panics halfway.
+pub struct TempDir {
+	path: PathBuf,
+}
+
+impl TempDir {
+	/// Create a new, empty temporary directory.
+	///
+	/// The name is only used to recognize the directory.
+	/// The directory name also contains the process ID and a counter,
+	/// and creation is retried with the next counter value if the directory already exists.
+	pub fn new(name: &str) -> Self {
+		static COUNTER: AtomicUsize = AtomicUsize::new(0);
+		loop {
+			let id = COUNTER.fetch_add(1, Ordering::Relaxed);
+			let path = std::env::temp_dir().join(format!("subst-test-{name}-{}-{id}", std::process::id()));
+			match std::fs::create_dir(&path) {
+				Ok(()) => return Self { path },
+				Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+				Err(e) => panic!("failed to create temporary directory {}: {e}", path.display()),
+			}
+		}
+	}
+
+	/// Get the path of the directory.
+	pub fn path(&self) -> &Path {
+		&self.path
+	}
+
+	/// Write a file in the directory, creating parent directories as needed.
+	///
+	/// Returns the path of the file.
+	pub fn write(&self, name: &str, data: impl AsRef<[u8]>) -> PathBuf {
+		let path = self.path.join(name);
+		if let Some(parent) = path.parent() {
+			std::fs::create_dir_all(parent).unwrap();
+		}
+		std::fs::write(&path, data).unwrap();
+		path
+	}
+}
+
+impl Drop for TempDir {
+	fn drop(&mut self) {
+		std::fs::remove_dir_all(&self.path).ok();
+	}
+}